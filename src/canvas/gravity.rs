@@ -0,0 +1,160 @@
+use bytemuck::{cast_slice, Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BufferBindingType, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor, ShaderStages};
+use crate::physics::Simulation;
+use super::planets::Planets;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Parameters {
+    count: u32,
+    step: f32,
+    softening: f32,
+    padding: u32,
+}
+
+//Leapfrog simulation that keeps its state on the GPU and writes the positions straight into the planet instances
+pub(super) struct GpuSimulation {
+    bind_group: BindGroup,
+    drift: ComputePipeline,
+    kick: ComputePipeline,
+    present: ComputePipeline,
+    count: u32,
+    //Body of every instance, the instances are the entries of the simulated frame
+    bodies: Vec<usize>,
+}
+
+impl GpuSimulation {
+    //Starts from the current state of the CPU simulation, converted to scene units so it fits into f32
+    pub(super) fn new(device: &Device, simulation: &Simulation, planets: &Planets) -> GpuSimulation {
+        let settings = simulation.settings();
+        let state = simulation.state();
+        let frame = simulation.frame();
+        let scale = planets.scale();
+        let accelerations = settings.force.accelerations(&state.positions, &state.masses, settings.gravity, settings.softening);
+
+        //Folding the constant and the mass together avoids values below the f32 range
+        let bodies = state.positions.iter().zip(&state.masses)
+            .map(|(position, mass)| {
+                let [x, y, z] = position.map(|value| (value * scale) as f32);
+                [x, y, z, (settings.gravity * mass * scale.powi(3)) as f32]
+            })
+            .collect::<Vec<_>>();
+        let to_scene = |vectors: &[[f64; 3]]| vectors.iter()
+            .map(|vector| {
+                let [x, y, z] = vector.map(|value| (value * scale) as f32);
+                [x, y, z, 0.0]
+            })
+            .collect::<Vec<_>>();
+
+        let parameters = Parameters {
+            count: bodies.len() as u32,
+            step: settings.step as f32,
+            softening: (settings.softening * scale).powi(2) as f32,
+            padding: 0
+        };
+
+        let storage = |contents: &[[f32; 4]]| device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(contents),
+            usage: BufferUsages::STORAGE
+        });
+        let parameter_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(&[parameters]),
+            usage: BufferUsages::UNIFORM
+        });
+        let body_buffer = storage(&bodies);
+        let velocity_buffer = storage(&to_scene(&state.velocities));
+        let acceleration_buffer = storage(&to_scene(&accelerations));
+
+        let entry = |binding: u32, ty: BufferBindingType| BindGroupLayoutEntry {
+            binding,
+            count: None,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None
+            }
+        };
+        let storage_type = BufferBindingType::Storage { read_only: false };
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                entry(0, BufferBindingType::Uniform),
+                entry(1, storage_type),
+                entry(2, storage_type),
+                entry(3, storage_type),
+                entry(4, storage_type)
+            ]
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry { binding: 0, resource: parameter_buffer.as_entire_binding() },
+                BindGroupEntry { binding: 1, resource: body_buffer.as_entire_binding() },
+                BindGroupEntry { binding: 2, resource: velocity_buffer.as_entire_binding() },
+                BindGroupEntry { binding: 3, resource: acceleration_buffer.as_entire_binding() },
+                BindGroupEntry { binding: 4, resource: planets.buffer.as_entire_binding() }
+            ]
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[]
+        });
+
+        let shader_module = device.create_shader_module(include_wgsl!("gravity.wgsl"));
+        let pipeline = |entry_point: &str| device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None
+        });
+
+        GpuSimulation {
+            bind_group,
+            drift: pipeline("drift"),
+            kick: pipeline("kick"),
+            present: pipeline("present"),
+            count: parameters.count,
+            bodies: (0..state.positions.len()).map(|entry| frame.body(entry)).collect()
+        }
+    }
+
+    pub(super) fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub(super) fn bodies(&self) -> &[usize] {
+        &self.bodies
+    }
+
+    //Records the given number of steps followed by copying the positions into the planet instances
+    pub(super) fn encode(&self, command_encoder: &mut CommandEncoder, steps: usize) {
+        let workgroups = self.count.div_ceil(WORKGROUP_SIZE);
+        let mut compute_pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None
+        });
+
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        for _ in 0..steps {
+            compute_pass.set_pipeline(&self.drift);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+            compute_pass.set_pipeline(&self.kick);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+
+        compute_pass.set_pipeline(&self.present);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+}
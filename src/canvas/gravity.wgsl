@@ -0,0 +1,61 @@
+//Leapfrog integration in scene units, every body is split into a drift and a kick dispatch
+//so no invocation reads a position while another one moves it
+
+struct Parameters {
+    count: u32,
+    step: f32,
+    softening: f32,
+}
+
+struct Instance {
+    position: vec4<f32>,
+    color: vec4<f32>,
+    radius: f32,
+}
+
+@group(0) @binding(0) var<uniform> parameters: Parameters;
+//xyz position, w gravitational constant times mass
+@group(0) @binding(1) var<storage, read_write> bodies: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> velocities: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read_write> accelerations: array<vec4<f32>>;
+@group(0) @binding(4) var<storage, read_write> instances: array<Instance>;
+
+@compute @workgroup_size(64) fn drift(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= parameters.count {
+        return;
+    }
+
+    let velocity = velocities[i].xyz + accelerations[i].xyz * parameters.step * 0.5;
+    velocities[i] = vec4<f32>(velocity, 0.0);
+    bodies[i] = vec4<f32>(bodies[i].xyz + velocity * parameters.step, bodies[i].w);
+}
+
+@compute @workgroup_size(64) fn kick(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= parameters.count {
+        return;
+    }
+
+    let position = bodies[i].xyz;
+    var acceleration = vec3<f32>(0.0);
+    for (var j = 0u; j < parameters.count; j++) {
+        let delta = bodies[j].xyz - position;
+        let distance = dot(delta, delta) + parameters.softening;
+        if j != i && distance > 0.0 {
+            acceleration += bodies[j].w * delta / (distance * sqrt(distance));
+        }
+    }
+
+    accelerations[i] = vec4<f32>(acceleration, 0.0);
+    velocities[i] = vec4<f32>(velocities[i].xyz + acceleration * parameters.step * 0.5, 0.0);
+}
+
+@compute @workgroup_size(64) fn present(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if i >= parameters.count {
+        return;
+    }
+
+    instances[i].position = vec4<f32>(bodies[i].xyz, 1.0);
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::f32::consts::PI;
 use super::Vertex;
 use bytemuck::cast_slice;
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
@@ -7,9 +8,14 @@ use wgpu::{Buffer, BufferUsages, Device};
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 struct Edge(usize, usize);
 
+//Vertex and texture coordinates of a triangle corner
+type Corner = (usize, [f32; 2]);
+
 #[derive(Clone, Debug)]
 pub(super) struct Icosphere {
     vertices: Vec<[f32; 3]>,
+    //Every corner the triangles index, vertices on the seam and the poles appear once for every distinct coordinate
+    corners: Vec<Corner>,
     indices: Vec<[usize; 3]>,
 }
 
@@ -36,7 +42,41 @@ impl Icosphere {
             indices = new_indices;
         }
 
-        Self { vertices, indices }
+        let (corners, indices) = Self::unwrap(&vertices, &indices);
+        Self { vertices, corners, indices }
+    }
+
+    //Equirectangular coordinates, u follows the longitude from -180° and v the colatitude from the north pole at +z.
+    //Triangles crossing the seam continue past u = 1 and the poles take the longitude of the triangle they belong to.
+    fn unwrap(vertices: &[[f32; 3]], indices: &[[usize; 3]]) -> (Vec<Corner>, Vec<[usize; 3]>) {
+        let pole = |[x, y, _]: [f32; 3]| x * x + y * y < 1e-8;
+        let mut corners = Vec::new();
+        let mut lookup = HashMap::new();
+        let new_indices = indices.iter()
+            .map(|triangle| {
+                let mut u = triangle.map(|vertex| 0.5 + vertices[vertex][1].atan2(vertices[vertex][0]) / (2.0 * PI));
+                let around = || (0..3).filter(|&corner| !pole(vertices[triangle[corner]]));
+                let (min, max) = around().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), corner| (min.min(u[corner]), max.max(u[corner])));
+                for corner in around().filter(|_| max - min > 0.5) {
+                    if u[corner] < 0.5 {
+                        u[corner] += 1.0;
+                    }
+                }
+                let longitude = around().map(|corner| u[corner]).sum::<f32>() / around().count() as f32;
+
+                std::array::from_fn(|corner| {
+                    let vertex = triangle[corner];
+                    let u = if pole(vertices[vertex]) { longitude } else { u[corner] };
+                    let v = vertices[vertex][2].clamp(-1.0, 1.0).acos() / PI;
+                    *lookup.entry((vertex, u.to_bits())).or_insert_with(|| {
+                        corners.push((vertex, [u, v]));
+                        corners.len() - 1
+                    })
+                })
+            })
+            .collect();
+
+        (corners, new_indices)
     }
 
     fn random(seed: usize) -> bool {
@@ -48,8 +88,12 @@ impl Icosphere {
     pub(super) fn vertex_buffer(&self, device: &Device) -> Buffer {
         let color = [0.3, 0.8, 0.5, 1.0];
 
-        let vertices = self.vertices.iter().enumerate()
-            .map(|(i, &[x, y, z])| Vertex { position: [x, y, z, 1.0], color: if Self::random(i) { color } else { [1.0; 4] } })
+        let vertices = self.corners.iter()
+            //On a unit sphere the normal is the position itself
+            .map(|&(i, uv)| {
+                let [x, y, z] = self.vertices[i];
+                Vertex { position: [x, y, z, 1.0], color: if Self::random(i) { color } else { [1.0; 4] }, normal: [x, y, z, 0.0], uv }
+            })
             .collect::<Vec<_>>();
 
         device.create_buffer_init(&BufferInitDescriptor {
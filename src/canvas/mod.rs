@@ -1,17 +1,31 @@
+use std::collections::BTreeMap;
 use bytemuck::{cast_slice, Pod, Zeroable};
 use cgmath::{perspective, Deg, Matrix4, Point3, Quaternion, Rotation, Rotation3, SquareMatrix, Vector3};
+use log::{info, warn};
+use gravity::GpuSimulation;
 use icosphere::Icosphere;
+use paths::Paths;
+use planets::{Lights, PlanetInstance, Planets, Size};
+use textures::Textures;
+use trails::Trails;
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::{include_wgsl, vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferUsages, Color, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Extent3d, FragmentState, FrontFace, IndexFormat, Limits, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StencilState, StoreOp, SurfaceConfiguration, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, VertexBufferLayout, VertexState, VertexStepMode};
-use wgpu::{Backends, Device, DeviceDescriptor, Instance, InstanceDescriptor, MemoryHints, PowerPreference, Queue, RequestAdapterOptions, Surface, WindowHandle};
+use wgpu::{include_wgsl, vertex_attr_array, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferUsages, Color, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Extent3d, FragmentState, FrontFace, IndexFormat, Limits, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StencilState, StoreOp, SurfaceConfiguration, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, VertexBufferLayout, VertexState, VertexStepMode};
+use wgpu::{Backends, Device, DeviceDescriptor, DownlevelFlags, Instance, InstanceDescriptor, MemoryHints, PowerPreference, Queue, RequestAdapterOptions, Surface, WindowHandle};
 use winit::dpi::PhysicalSize;
 
-use crate::system::{PlanetInstance, System};
+use crate::physics::{ForceEvaluator, Integrator};
+use crate::system::System;
+use crate::trajectory::Trajectory;
 
+mod gravity;
 mod icosphere;
+mod paths;
+mod planets;
+mod textures;
+mod trails;
 
 pub(super) struct Canvas {
-    pub(super) device: Device,
+    device: Device,
     queue: Queue,
     surface: Surface<'static>,
     config: SurfaceConfiguration,
@@ -24,8 +38,21 @@ pub(super) struct Canvas {
     index_buffer: Buffer,
     vertex_buffer: Buffer,
     camera_buffer: Buffer,
+    light_buffer: Buffer,
+    planets: Planets,
+    textures: Textures,
+    size: Size,
+    trails: Trails,
+    //Jumps of the playback the trails have followed, a new one starts them over
+    jumps: usize,
+    paths: Option<Paths>,
+    show_paths: bool,
+    path_layout: BindGroupLayout,
+    gpu_simulation: Option<GpuSimulation>,
+    compute: bool,
     render: RenderPipeline,
     grid_render: RenderPipeline,
+    path_render: RenderPipeline,
     index_count: u32,
 }
 
@@ -34,6 +61,8 @@ pub(super) struct Canvas {
 struct Vertex {
     position: [f32; 4],
     color: [f32; 4],
+    normal: [f32; 4],
+    uv: [f32; 2],
 }
 
 #[repr(C)]
@@ -68,11 +97,19 @@ impl Canvas {
             compatible_surface: Some(&surface)
         }).await.unwrap();
 
+        //WebGL2 has no compute shaders, everywhere else the limits allow the GPU simulation
+        let compute = adapter.get_downlevel_capabilities().flags.contains(DownlevelFlags::COMPUTE_SHADERS);
+        let required_limits = if compute {
+            Limits::downlevel_defaults()
+        } else {
+            Limits::downlevel_webgl2_defaults()
+        };
+
         let (device, queue) = adapter.request_device(&DeviceDescriptor {
             label: None,
             memory_hints: MemoryHints::Performance,
             required_features: Default::default(),
-            required_limits: Limits::downlevel_webgl2_defaults()
+            required_limits
         }, None).await.unwrap();
 
         let config = surface.get_default_config(&adapter, size.width.max(1), size.height.max(1)).unwrap();
@@ -88,10 +125,18 @@ impl Canvas {
             contents: cast_slice(&[Camera::zeroed()])
         });
 
+        let light_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            contents: cast_slice(&[Lights::zeroed()])
+        });
+
         let shader_code = include_wgsl!("shader.wgsl");
         let grid_shader_code = include_wgsl!("grid_shader.wgsl");
+        let path_shader_code = include_wgsl!("paths.wgsl");
         let shader_module = device.create_shader_module(shader_code);
         let grid_shader_module = device.create_shader_module(grid_shader_code);
+        let path_shader_module = device.create_shader_module(path_shader_code);
 
         let camera_bind_group_layout_entry = BindGroupLayoutEntry {
             binding: 0,
@@ -104,26 +149,60 @@ impl Canvas {
             }
         };
 
+        let light_bind_group_layout_entry = BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ..camera_bind_group_layout_entry
+        };
+
         let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: None,
-            entries: &[camera_bind_group_layout_entry]
+            entries: &[camera_bind_group_layout_entry, light_bind_group_layout_entry]
         });
 
         let bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: None,
             layout: &bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding()
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: light_buffer.as_entire_binding()
+                }
+            ]
+        });
+
+        let path_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                visibility: ShaderStages::FRAGMENT,
+                ..camera_bind_group_layout_entry
             }]
         });
 
+        let textures = Textures::new(&device, config.format.is_srgb());
+
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: None,
             bind_group_layouts: &[&bind_group_layout],
             push_constant_ranges: &[]
         });
 
+        let planet_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout, &textures.layout],
+            push_constant_ranges: &[]
+        });
+
+        let path_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout, &path_layout],
+            push_constant_ranges: &[]
+        });
+
         let vertex_state = VertexState {
             buffers: &[Vertex::desc(), PlanetInstance::desc()],
             module: &shader_module, 
@@ -138,6 +217,13 @@ impl Canvas {
             compilation_options: Default::default()
         };
 
+        let path_vertex_state = VertexState {
+            buffers: &[PlanetInstance::vertex_desc()],
+            module: &path_shader_module,
+            entry_point: Some("vertex"),
+            compilation_options: Default::default()
+        };
+
         let fragment_state = FragmentState {
             module: &shader_module,
             entry_point: Some("fragment"),
@@ -160,6 +246,17 @@ impl Canvas {
             })]
         };
 
+        let path_fragment_state = FragmentState {
+            module: &path_shader_module,
+            entry_point: Some("fragment"),
+            compilation_options: Default::default(),
+            targets: &[Some(ColorTargetState {
+                format: config.format,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::ALL
+            })]
+        };
+
         let multisample_state = MultisampleState {
             count: 1,
             mask: !0,
@@ -186,7 +283,7 @@ impl Canvas {
 
         let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
+            layout: Some(&planet_pipeline_layout),
             vertex: vertex_state,
             fragment: Some(fragment_state),
             cache: None,
@@ -202,13 +299,35 @@ impl Canvas {
             vertex: grid_vertex_state,
             fragment: Some(grid_fragment_state),
             cache: None,
-            depth_stencil: Some(depth_stencil_state),
+            depth_stencil: Some(depth_stencil_state.clone()),
             multisample: multisample_state,
             multiview: None,
             primitive: primitive_state,
         });
 
+        //The paths are drawn after the planets and don't write depth, so they never hide them
+        let path_render = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&path_pipeline_layout),
+            vertex: path_vertex_state,
+            fragment: Some(path_fragment_state),
+            cache: None,
+            depth_stencil: Some(DepthStencilState {
+                depth_write_enabled: false,
+                ..depth_stencil_state.clone()
+            }),
+            multisample: multisample_state,
+            multiview: None,
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::LineList,
+                ..primitive_state
+            },
+        });
+
+        let trails = Trails::new(&device, &bind_group_layout, config.format, depth_stencil_state);
         let depth_texture = Self::create_depth_texture(&device, config.width, config.height);
+        let size = Size::default();
+        let planets = Planets::new(&device, &Trajectory::default(), Vec::new(), compute, size);
 
         Canvas {
             device,
@@ -222,9 +341,82 @@ impl Canvas {
             index_buffer,
             vertex_buffer,
             camera_buffer,
+            light_buffer,
+            planets,
+            textures,
+            size,
+            trails,
+            jumps: 0,
+            paths: None,
+            show_paths: false,
+            path_layout,
+            gpu_simulation: None,
+            compute,
             render: render_pipeline,
             index_count,
-            grid_render
+            grid_render,
+            path_render
+        }
+    }
+
+    //Recreates the resources derived from the loaded trajectory, the images are the textures the bodies may use by name
+    pub(super) fn load(&mut self, system: &mut System, images: &BTreeMap<String, Vec<u8>>) {
+        let layers = self.textures.load(&self.device, &self.queue, system.trajectory(), images);
+        self.planets = Planets::new(&self.device, system.trajectory(), layers, self.compute, self.size);
+        self.gpu_simulation = None;
+        self.load_simulation(system);
+
+        //Trails of the GPU simulation follow its instances, the others are keyed by body id
+        let bodies = match &self.gpu_simulation {
+            Some(gpu_simulation) => gpu_simulation.count() as usize,
+            None => system.trajectory().largest_frame()
+                .map_or(0, |frame| frame.positions.len())
+                .max(system.trajectory().bodies.len())
+        };
+        self.trails.reset(&self.device, bodies);
+        self.jumps = system.jumps();
+        self.paths = Paths::new(&self.device, &self.path_layout, system.trajectory(), &self.planets);
+    }
+
+    pub(super) fn next_size_mode(&mut self) {
+        self.size.mode = self.size.mode.next();
+        info!("Sizes: {}", self.size.mode);
+        self.resize();
+    }
+
+    pub(super) fn exaggerate(&mut self, factor: f32) {
+        self.size.exaggeration *= factor;
+        info!("Sizes exaggerated {}x", self.size.exaggeration);
+        self.resize();
+    }
+
+    fn resize(&mut self) {
+        let gpu_instances = self.gpu_simulation.as_ref().map(GpuSimulation::count);
+        self.planets.resize(&self.queue, self.size, gpu_instances);
+    }
+
+    pub(super) fn toggle_trails(&mut self) {
+        self.trails.toggle();
+    }
+
+    pub(super) fn toggle_paths(&mut self) {
+        self.show_paths = !self.show_paths;
+        info!("Paths: {}", if self.show_paths { "on" } else { "off" });
+    }
+
+    fn load_simulation(&mut self, system: &mut System) {
+        let Some(settings) = system.trajectory().simulation.filter(|settings| settings.gpu) else { return };
+        if !self.compute {
+            warn!("Compute shaders are not available, simulating on the CPU");
+        } else if settings.integrator != Integrator::Leapfrog || settings.force != ForceEvaluator::Direct {
+            warn!("The GPU only simulates leapfrog with direct summation, simulating on the CPU");
+        } else if let Some(simulation) = system.offload_simulation() {
+            //The compute shader only moves the planets, the colors and lights are written once up front
+            let frame = simulation.frame();
+            self.planets.upload(&self.device, &self.queue, &frame);
+            self.queue.write_buffer(&self.light_buffer, 0, cast_slice(&[self.planets.lights(&frame)]));
+            self.gpu_simulation = Some(GpuSimulation::new(&self.device, simulation, &self.planets));
+            warn!("Simulating on the GPU: reverse, seeking, end of data modes, events, diagnostics and orbital elements are disabled");
         }
     }
 
@@ -237,10 +429,33 @@ impl Canvas {
             self.config_changed = false;
         }
 
+        let mut command_encoder = self.device.create_command_encoder(&Default::default());
+        let planet_count = match &self.gpu_simulation {
+            Some(gpu_simulation) => {
+                let steps = system.offloaded_steps();
+                gpu_simulation.encode(&mut command_encoder, steps);
+                if steps > 0 {
+                    self.trails.copy(&mut command_encoder, &self.planets);
+                    self.planets.copy_lights(&mut command_encoder, &self.light_buffer, gpu_simulation.bodies());
+                }
+                gpu_simulation.count()
+            }
+            None => {
+                let frame = system.step();
+                if system.jumps() != self.jumps {
+                    self.jumps = system.jumps();
+                    self.trails.clear();
+                }
+                let count = self.planets.upload(&self.device, &self.queue, &frame);
+                self.queue.write_buffer(&self.light_buffer, 0, cast_slice(&[self.planets.lights(&frame)]));
+                self.trails.push(&self.queue, &frame, &self.planets);
+                count
+            }
+        };
+
         let texture = self.surface.get_current_texture().unwrap();
         let view = texture.texture.create_view(&Default::default());
         let depth_view = self.depth_texture.create_view(&Default::default());
-        let mut command_encoder = self.device.create_command_encoder(&Default::default());
         let mut render_pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
             label: None,
             depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
@@ -287,10 +502,19 @@ impl Canvas {
 
         render_pass.set_pipeline(&self.render);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, system.planet_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.planets.buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint32);
         render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.draw_indexed(0..self.index_count, 0, 0..system.step(&self.queue));
+        render_pass.set_bind_group(1, &self.textures.bind_group, &[]);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..planet_count);
+
+        self.trails.draw(&self.queue, &mut render_pass, &self.bind_group);
+
+        if let Some(paths) = self.paths.as_ref().filter(|_| self.show_paths) {
+            render_pass.set_pipeline(&self.path_render);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            paths.draw(&self.queue, &mut render_pass, system.time());
+        }
 
         render_pass.set_pipeline(&self.grid_render);
         render_pass.set_bind_group(0, &self.bind_group, &[]);
@@ -351,7 +575,9 @@ impl Vertex {
             attributes: const {
                 &vertex_attr_array![
                     0 => Float32x4,
-                    1 => Float32x4
+                    1 => Float32x4,
+                    6 => Float32x4,
+                    7 => Float32x2
                 ]
             }
         }
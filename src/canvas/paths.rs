@@ -0,0 +1,113 @@
+use bytemuck::{cast_slice, Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, Buffer, BufferUsages, Device, IndexFormat, Queue, RenderPass};
+use crate::trajectory::Trajectory;
+use super::planets::{PlanetInstance, Planets};
+
+//Frames are skipped evenly so that no more vertices than this are uploaded
+const MAX_VERTICES: usize = 1 << 21;
+//Fraction of the whole recording highlighted around the current time
+const HIGHLIGHT: f64 = 0.01;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Parameters {
+    time: f32,
+    window: f32,
+    padding: [f32; 2],
+}
+
+//Lines through the positions of every body over all loaded frames, built once when a trajectory is loaded.
+//The w component of a position is its time since the first frame.
+pub(super) struct Paths {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    parameter_buffer: Buffer,
+    bind_group: BindGroup,
+    index_count: u32,
+    start: f64,
+    window: f32,
+}
+
+impl Paths {
+    pub(super) fn new(device: &Device, layout: &BindGroupLayout, trajectory: &Trajectory, planets: &Planets) -> Option<Paths> {
+        let bodies = trajectory.largest_frame()?.positions.len().max(trajectory.bodies.len());
+        let stride = (trajectory.len() * bodies).div_ceil(MAX_VERTICES).max(1);
+        let start = trajectory.start_time();
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        //Vertex of every body in the previous sampled frame, bodies missing from a frame break their line
+        let mut previous = vec![None; bodies];
+        for index in (0..trajectory.len()).step_by(stride) {
+            let (Some(frame), Some(time)) = (trajectory.frame(index), trajectory.time(index)) else { continue };
+            let mut current = vec![None; bodies];
+            for (entry, position) in frame.positions.iter().enumerate() {
+                let body = frame.body(entry);
+                let [x, y, z] = position.map(|value| (value * planets.scale()) as f32);
+                let vertex = vertices.len() as u32;
+                vertices.push(PlanetInstance::new([x, y, z, (time - start) as f32], planets.color(body)));
+                if let Some(last) = previous.get(body).copied().flatten() {
+                    indices.extend([last, vertex]);
+                }
+                if let Some(slot) = current.get_mut(body) {
+                    *slot = Some(vertex);
+                }
+            }
+            previous = current;
+        }
+
+        if indices.is_empty() {
+            return None;
+        }
+
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(&vertices),
+            usage: BufferUsages::VERTEX
+        });
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(&indices),
+            usage: BufferUsages::INDEX
+        });
+        let parameter_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(&[Parameters::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: parameter_buffer.as_entire_binding()
+            }]
+        });
+
+        Some(Paths {
+            vertex_buffer,
+            index_buffer,
+            parameter_buffer,
+            bind_group,
+            index_count: indices.len() as u32,
+            start,
+            window: ((trajectory.end_time() - start) * HIGHLIGHT) as f32
+        })
+    }
+
+    //Draws with the pipeline already set, highlighting the given time
+    pub(super) fn draw(&self, queue: &Queue, render_pass: &mut RenderPass, time: f64) {
+        queue.write_buffer(&self.parameter_buffer, 0, cast_slice(&[Parameters {
+            time: (time - self.start) as f32,
+            window: self.window,
+            padding: [0.0; 2]
+        }]));
+
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
@@ -0,0 +1,51 @@
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> path: Path;
+
+struct Camera {
+    projection: mat4x4<f32>,
+    position: vec4<f32>
+}
+
+struct Path {
+    time: f32,
+    window: f32,
+    padding: vec2<f32>,
+}
+
+struct VertexInput {
+    @location(0) position: vec4<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) time: f32,
+}
+
+struct FragmentInput {
+    @location(0) color: vec4<f32>,
+    @location(1) time: f32,
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>
+}
+
+@vertex fn vertex(input: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = camera.projection * vec4<f32>(input.position.xyz, 1.0);
+    output.color = vec4<f32>(input.color.xyz, 1.0);
+    output.time = input.position.w;
+    return output;
+}
+
+@fragment fn fragment(input: FragmentInput) -> FragmentOutput {
+    var output: FragmentOutput;
+    output.color = input.color;
+    //The stretch around the current position is highlighted, the part still ahead is dimmer than the one behind
+    if abs(input.time - path.time) > path.window {
+        output.color.w = select(0.2, 0.5, input.time <= path.time);
+    }
+    return output;
+}
@@ -0,0 +1,322 @@
+use std::fmt::{Display, Formatter};
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{vertex_attr_array, Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Queue, VertexBufferLayout, VertexStepMode};
+use crate::trajectory::{Frame, Trajectory};
+
+//Radius of every body in uniform mode and of the largest one in logarithmic mode, in scene units
+const UNIFORM_RADIUS: f32 = 0.5;
+//Density used to estimate the radius of bodies that only have a mass, roughly that of a rocky planet
+const DEFAULT_DENSITY: f64 = 5500.0;
+
+//Lights the shader can take into account, the brightest bodies win
+pub(super) const MAX_LIGHTS: usize = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(super) struct PlanetInstance {
+    position: [f32; 4],
+    color: [f32; 4],
+    //Radius in scene units, padded to the 16 byte alignment the compute shader expects
+    radius: f32,
+    //Intensity of a body that lights the others, it isn't shaded itself
+    light: f32,
+    //Layer of the texture array wrapped around the body, negative for none
+    texture: f32,
+    padding: f32,
+}
+
+//Positions of the light-emitting bodies in scene units
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(super) struct Lights {
+    positions: [[f32; 4]; MAX_LIGHTS],
+    intensities: [f32; MAX_LIGHTS],
+    count: u32,
+    padding: [u32; 3],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) enum SizeMode {
+    //Radii at the same scale as the positions
+    TrueScale,
+    Uniform,
+    //Radii grow with the logarithm of the physical radius, so small bodies stay visible next to large ones
+    #[default]
+    Logarithmic
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct Size {
+    pub(super) mode: SizeMode,
+    //Factor every radius is multiplied with
+    pub(super) exaggeration: f32,
+}
+
+//Instance buffer of the planets, filled from the positions the system plays back
+pub(super) struct Planets {
+    pub(super) buffer: Buffer,
+    capacity: usize,
+    colors: Vec<[f32; 4]>,
+    //Physical radius of every body if it is known or can be estimated, and the radius drawn in scene units
+    physical_radii: Vec<Option<f64>>,
+    radii: Vec<f32>,
+    //Body and intensity of every light
+    lights: Vec<(usize, f32)>,
+    textures: Vec<Option<u32>>,
+    scale: f64,
+    usage: BufferUsages,
+}
+
+impl Planets {
+    //Storage usage lets a compute shader write the instances directly
+    pub(super) fn new(device: &Device, trajectory: &Trajectory, textures: Vec<Option<u32>>, storage: bool, size: Size) -> Planets {
+        let mut starting_planets = trajectory.largest_frame()
+            .map(|frame| frame.positions.iter()
+                .map(|&[x, y, z]| PlanetInstance::new([x as f32, y as f32, z as f32, 1.0], [1.0, 1.0, 1.0, 1.0]))
+                .collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        if starting_planets.is_empty() {
+            starting_planets.push(PlanetInstance::new([0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0]));
+        }
+
+        let max_distance = starting_planets.iter()
+            .map(|planet| (planet.position[..3].iter().map(|&x| x * x).sum::<f32>()).sqrt())
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap_or(1.0);
+
+        let scale = if max_distance.abs() > 0.01 {
+            10.0 / max_distance as f64
+        } else {
+            1.0
+        };
+
+        let mut colors = Vec::new();
+        let mut physical_radii = Vec::new();
+        for i in 0..trajectory.bodies.len().max(starting_planets.len()) {
+            //The alpha channel tells the shader whether the color was chosen explicitly
+            let color = trajectory.bodies.get(i).and_then(|body| body.color).unwrap_or_else(|| {
+                let mut random = Self::random(i as u32).to_ne_bytes().map(|b| b as f32 / 256.0);
+                random[3] = 0.0;
+                random
+            });
+            colors.push(color);
+
+            let body = trajectory.bodies.get(i);
+            let estimate = body.and_then(|body| body.mass).map(|mass| (3.0 * mass / (4.0 * std::f64::consts::PI * DEFAULT_DENSITY)).cbrt());
+            physical_radii.push(body.and_then(|body| body.radius).or(estimate).filter(|&radius| radius > 0.0));
+        }
+
+        //Without explicit lights a body holding most of the mass is taken for a star
+        let mut lights = trajectory.bodies.iter().enumerate()
+            .filter_map(|(body, properties)| Some((body, properties.light? as f32)))
+            .filter(|&(_, intensity)| intensity > 0.0)
+            .collect::<Vec<_>>();
+        if lights.is_empty() {
+            let masses = trajectory.bodies.iter().map(|body| body.mass.unwrap_or_default());
+            let total = masses.clone().sum::<f64>();
+            let heaviest = masses.enumerate().max_by(|a, b| a.1.total_cmp(&b.1));
+            lights.extend(heaviest.filter(|&(_, mass)| mass > total / 2.0).map(|(body, _)| (body, 1.0)));
+        }
+        lights.sort_by(|a, b| b.1.total_cmp(&a.1));
+        lights.truncate(MAX_LIGHTS);
+
+        //Copy source for the trails of the GPU simulation
+        let mut usage = BufferUsages::VERTEX | BufferUsages::COPY_DST | BufferUsages::COPY_SRC;
+        if storage {
+            usage |= BufferUsages::STORAGE;
+        }
+
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&starting_planets),
+            usage
+        });
+
+        let mut planets = Planets {
+            buffer,
+            capacity: starting_planets.len(),
+            colors,
+            physical_radii,
+            radii: Vec::new(),
+            lights,
+            textures,
+            scale,
+            usage
+        };
+        planets.radii = planets.scene_radii(size);
+        planets
+    }
+
+    //Factor from trajectory units to scene units
+    pub(super) fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    //Color of a body, the alpha channel tells whether it was chosen explicitly
+    pub(super) fn color(&self, body: usize) -> [f32; 4] {
+        self.colors.get(body).copied().unwrap_or([1.0; 4])
+    }
+
+    //Applies a new size to the next upload, the instances a compute shader owns get their radius written right away
+    pub(super) fn resize(&mut self, queue: &Queue, size: Size, gpu_instances: Option<u32>) {
+        self.radii = self.scene_radii(size);
+        for instance in 0..gpu_instances.unwrap_or_default() as usize {
+            let offset = instance * size_of::<PlanetInstance>() + std::mem::offset_of!(PlanetInstance, radius);
+            queue.write_buffer(&self.buffer, offset as u64, bytemuck::bytes_of(&self.radius(instance)));
+        }
+    }
+
+    //The lights that are in the frame, in the order of their intensity
+    pub(super) fn lights(&self, frame: &Frame) -> Lights {
+        let mut lights = Lights::zeroed();
+        for &(body, intensity) in &self.lights {
+            let Some(entry) = (0..frame.positions.len()).find(|&entry| frame.body(entry) == body) else { continue };
+            let light = lights.count as usize;
+            let [x, y, z] = frame.positions[entry].map(|value| (value * self.scale) as f32);
+            lights.positions[light] = [x, y, z, 1.0];
+            lights.intensities[light] = intensity;
+            lights.count += 1;
+        }
+        lights
+    }
+
+    //Copies the light positions out of the instances a compute shader moves, given the body of every instance.
+    //Lights that aren't simulated are left out like in lights.
+    pub(super) fn copy_lights(&self, command_encoder: &mut CommandEncoder, buffer: &Buffer, bodies: &[usize]) {
+        let position = size_of::<[f32; 4]>() as u64;
+        let entries = self.lights.iter().filter_map(|&(body, _)| bodies.iter().position(|&other| other == body));
+        for (light, entry) in entries.enumerate() {
+            let offset = (entry * size_of::<PlanetInstance>()) as u64;
+            command_encoder.copy_buffer_to_buffer(&self.buffer, offset, buffer, light as u64 * position, position);
+        }
+    }
+
+    fn light(&self, body: usize) -> f32 {
+        self.lights.iter().find(|&&(light, _)| light == body).map_or(0.0, |&(_, intensity)| intensity)
+    }
+
+    fn texture(&self, body: usize) -> f32 {
+        self.textures.get(body).copied().flatten().map_or(-1.0, |layer| layer as f32)
+    }
+
+    fn radius(&self, body: usize) -> f32 {
+        self.radii.get(body).copied().unwrap_or(UNIFORM_RADIUS)
+    }
+
+    //Bodies without a known radius are drawn as large as in uniform mode
+    fn scene_radii(&self, size: Size) -> Vec<f32> {
+        let known = || self.physical_radii.iter().flatten().copied();
+        let smallest = known().fold(f64::INFINITY, f64::min);
+        let decades = (known().fold(0.0, f64::max) / smallest).log10() + 1.0;
+        self.physical_radii.iter()
+            .map(|radius| {
+                let radius = match (size.mode, radius) {
+                    (SizeMode::TrueScale, Some(radius)) => (radius * self.scale) as f32,
+                    (SizeMode::Logarithmic, Some(radius)) => UNIFORM_RADIUS * (((radius / smallest).log10() + 1.0) / decades) as f32,
+                    _ => UNIFORM_RADIUS
+                };
+                radius * size.exaggeration
+            })
+            .collect()
+    }
+
+    fn random(seed: u32) -> u32 {
+        let state = seed;
+        state.wrapping_mul(1664525).wrapping_add(1013904223)
+    }
+
+    //Writes the bodies of the frame into the instance buffer and returns the number of instances to draw,
+    //the colors follow the body ids so they stay with their body when others appear or disappear
+    pub(super) fn upload(&mut self, device: &Device, queue: &Queue, frame: &Frame) -> u32 {
+        let planets = frame.positions.iter()
+            .enumerate()
+            .map(|(entry, &[x, y, z])| {
+                let body = frame.body(entry);
+                let position = [(x * self.scale) as f32, (y * self.scale) as f32, (z * self.scale) as f32, 1.0];
+                PlanetInstance {
+                    radius: self.radius(body),
+                    light: self.light(body),
+                    texture: self.texture(body),
+                    ..PlanetInstance::new(position, self.color(body))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if planets.len() > self.capacity {
+            self.buffer = device.create_buffer(&BufferDescriptor {
+                label: None,
+                size: size_of_val(planets.as_slice()) as u64,
+                usage: self.usage,
+                mapped_at_creation: false
+            });
+            self.capacity = planets.len();
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(planets.as_slice()));
+        planets.len() as u32
+    }
+}
+
+impl PlanetInstance {
+    pub(super) fn new(position: [f32; 4], color: [f32; 4]) -> PlanetInstance {
+        PlanetInstance { position, color, radius: 0.0, light: 0.0, texture: -1.0, padding: 0.0 }
+    }
+
+    pub(super) fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: const {
+                &vertex_attr_array![
+                    2 => Float32x4,
+                    3 => Float32x4,
+                    4 => Float32,
+                    5 => Float32,
+                    8 => Float32
+                ]
+            }
+        }
+    }
+
+    //The same data read as vertices, the trails connect the rows of instances with lines
+    pub(super) fn vertex_desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: size_of::<Self>() as u64,
+            step_mode: VertexStepMode::Vertex,
+            attributes: const {
+                &vertex_attr_array![
+                    0 => Float32x4,
+                    1 => Float32x4
+                ]
+            }
+        }
+    }
+}
+
+impl SizeMode {
+    pub(super) fn next(self) -> SizeMode {
+        match self {
+            SizeMode::TrueScale => SizeMode::Uniform,
+            SizeMode::Uniform => SizeMode::Logarithmic,
+            SizeMode::Logarithmic => SizeMode::TrueScale
+        }
+    }
+}
+
+impl Default for Size {
+    fn default() -> Size {
+        Size { mode: SizeMode::default(), exaggeration: 1.0 }
+    }
+}
+
+impl Display for SizeMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SizeMode::TrueScale => write!(f, "true scale"),
+            SizeMode::Uniform => write!(f, "uniform"),
+            SizeMode::Logarithmic => write!(f, "logarithmic")
+        }
+    }
+}
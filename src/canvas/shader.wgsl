@@ -1,40 +1,100 @@
 @group(0) @binding(0) var<uniform> camera: Camera;
+@group(0) @binding(1) var<uniform> lights: Lights;
+@group(1) @binding(0) var textures: texture_2d_array<f32>;
+@group(1) @binding(1) var texture_sampler: sampler;
 
 struct Camera {
     projection: mat4x4<f32>,
     position: vec4<f32>
 }
 
+struct Lights {
+    positions: array<vec4<f32>, 4>,
+    intensities: vec4<f32>,
+    count: u32,
+}
+
 struct VertexInput {
     @location(0) position: vec4<f32>,
     @location(1) color: vec4<f32>,
+    @location(6) normal: vec4<f32>,
+    @location(7) uv: vec2<f32>,
     @location(2) offset: vec4<f32>,
     @location(3) color_offset: vec4<f32>,
+    @location(4) radius: f32,
+    @location(5) light: f32,
+    @location(8) texture: f32,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) color: vec4<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) light: f32,
+    @location(4) uv: vec2<f32>,
+    @location(5) @interpolate(flat) texture: f32,
 }
 
 struct FragmentInput {
     @location(0) color: vec4<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) light: f32,
+    @location(4) uv: vec2<f32>,
+    @location(5) @interpolate(flat) texture: f32,
 }
 
 struct FragmentOutput {
     @location(0) color: vec4<f32>
 }
 
+const AMBIENT: f32 = 0.08;
+const SPECULAR: f32 = 0.3;
+const SHININESS: f32 = 32.0;
+
 @vertex fn vertex(input: VertexInput) -> VertexOutput {
     var output: VertexOutput;
-    output.position = camera.projection * (input.position + input.offset);
-    output.color = (input.color + input.color_offset) % 1.0;
-    output.color.w = 1.0;
+    output.world_position = input.position.xyz * input.radius + input.offset.xyz;
+    output.position = camera.projection * vec4<f32>(output.world_position, 1.0);
+    output.normal = input.normal.xyz;
+    output.light = input.light;
+    output.uv = input.uv;
+    output.texture = input.texture;
+    if input.color_offset.w > 0.0 {
+        let shade = dot(input.color.xyz, vec3<f32>(0.299, 0.587, 0.114));
+        output.color = vec4<f32>(input.color_offset.xyz * shade, 1.0);
+    } else {
+        output.color = (input.color + input.color_offset) % 1.0;
+        output.color.w = 1.0;
+    }
     return output;
 }
 
+//Diffuse and Blinn-Phong specular light from every star, stars themselves and scenes without stars aren't shaded.
+//The texture is sampled for every body so the sampling stays in uniform control flow, bodies without one ignore it.
 @fragment fn fragment(input: FragmentInput) -> FragmentOutput {
     var output: FragmentOutput;
-    output.color = input.color;
+    let texel = textureSample(textures, texture_sampler, input.uv, i32(max(input.texture, 0.0)));
+    output.color = select(input.color, vec4<f32>(texel.xyz, 1.0), input.texture >= 0.0);
+    if input.light > 0.0 || lights.count == 0u {
+        return output;
+    }
+
+    let normal = normalize(input.normal);
+    let view = normalize(camera.position.xyz - input.world_position);
+    var diffuse = AMBIENT;
+    var specular = 0.0;
+    for (var i = 0u; i < lights.count; i++) {
+        let direction = normalize(lights.positions[i].xyz - input.world_position);
+        let intensity = lights.intensities[i];
+        let lambert = max(dot(normal, direction), 0.0);
+        diffuse += lambert * intensity;
+        if lambert > 0.0 {
+            specular += pow(max(dot(normal, normalize(direction + view)), 0.0), SHININESS) * SPECULAR * intensity;
+        }
+    }
+
+    output.color = vec4<f32>(output.color.xyz * diffuse + vec3<f32>(specular), 1.0);
     return output;
-}
\ No newline at end of file
+}
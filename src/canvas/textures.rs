@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use image::imageops::{self, FilterType};
+use log::{info, warn};
+use wgpu::{AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Device, Extent3d, FilterMode, Origin3d, Queue, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension};
+use crate::trajectory::{Body, Trajectory};
+
+//Size every image is scaled to, a layer of the array, within the limits of WebGL2
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 512;
+//WebGL2 takes a texture with a single layer for a plain 2D texture, so the array always has two
+const MIN_LAYERS: u32 = 2;
+//Every layer takes 2 MB, more images than this are left out even if the device could hold them
+const MAX_LAYERS: u32 = 32;
+
+//Equirectangular images of the bodies, stacked into one texture array so a single draw covers every planet
+pub(super) struct Textures {
+    pub(super) layout: BindGroupLayout,
+    pub(super) bind_group: BindGroup,
+    sampler: Sampler,
+    format: TextureFormat,
+}
+
+impl Textures {
+    //The texels are decoded as sRGB if the surface encodes its output again, otherwise they are used as they are
+    pub(super) fn new(device: &Device, srgb: bool) -> Textures {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    count: None,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2Array,
+                        multisampled: false
+                    }
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    count: None,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering)
+                }
+            ]
+        });
+
+        //The longitude wraps around, triangles on the seam continue past u = 1
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: None,
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let format = if srgb { TextureFormat::Rgba8UnormSrgb } else { TextureFormat::Rgba8Unorm };
+        let bind_group = Self::bind_group(device, &layout, &sampler, &Self::texture(device, format, 1, 1, MIN_LAYERS));
+        Textures { layout, bind_group, sampler, format }
+    }
+
+    //Uploads the images of the bodies and returns the layer of every body, bodies sharing an image share its layer.
+    //A body uses the image its texture attribute names, or without one an image named after the body, e.g. Earth.jpg.
+    pub(super) fn load(&mut self, device: &Device, queue: &Queue, trajectory: &Trajectory, images: &BTreeMap<String, Vec<u8>>) -> Vec<Option<u32>> {
+        let max_layers = device.limits().max_texture_array_layers.min(MAX_LAYERS);
+        let mut names = Vec::<&str>::new();
+        let mut layers = Vec::new();
+        for body in &trajectory.bodies {
+            let layer = match (Self::image(body, images), &body.texture) {
+                (Some(name), _) => match names.iter().position(|&other| other == name) {
+                    Some(layer) => Some(layer as u32),
+                    None if names.len() < max_layers as usize => {
+                        names.push(name);
+                        Some(names.len() as u32 - 1)
+                    }
+                    None => {
+                        warn!("{name}: at most {max_layers} textures can be shown at once");
+                        None
+                    }
+                },
+                (None, Some(texture)) => {
+                    warn!("{texture}: image not found");
+                    None
+                }
+                (None, None) => None
+            };
+            layers.push(layer);
+        }
+
+        let mut decoded = Vec::new();
+        for (layer, name) in names.iter().enumerate() {
+            match image::load_from_memory(&images[*name]) {
+                Ok(image) => decoded.push(imageops::resize(&image.to_rgba8(), WIDTH, HEIGHT, FilterType::Triangle)),
+                Err(err) => {
+                    warn!("{name}: {err}");
+                    layers.iter_mut().filter(|other| **other == Some(layer as u32)).for_each(|other| *other = None);
+                    //Keeps the layers of the other images in place
+                    decoded.push(Default::default());
+                }
+            }
+        }
+
+        let texture = Self::texture(device, self.format, WIDTH, HEIGHT, (decoded.len() as u32).max(MIN_LAYERS));
+        for (layer, image) in decoded.iter().enumerate().filter(|(_, image)| !image.is_empty()) {
+            queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d { x: 0, y: 0, z: layer as u32 },
+                    aspect: TextureAspect::All
+                },
+                image.as_raw(),
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * WIDTH),
+                    rows_per_image: Some(HEIGHT)
+                },
+                Extent3d { width: WIDTH, height: HEIGHT, depth_or_array_layers: 1 }
+            );
+        }
+
+        if !names.is_empty() {
+            info!("Textures: {}", names.join(", "));
+        }
+        self.bind_group = Self::bind_group(device, &self.layout, &self.sampler, &texture);
+        layers
+    }
+
+    fn image<'a>(body: &Body, images: &'a BTreeMap<String, Vec<u8>>) -> Option<&'a str> {
+        match &body.texture {
+            Some(texture) => images.get_key_value(texture).map(|(name, _)| name.as_str()),
+            None => {
+                let body_name = body.name.as_deref()?;
+                images.keys()
+                    .find(|name| Path::new(name).file_stem().and_then(OsStr::to_str).is_some_and(|stem| stem.eq_ignore_ascii_case(body_name)))
+                    .map(String::as_str)
+            }
+        }
+    }
+
+    fn texture(device: &Device, format: TextureFormat, width: u32, height: u32, layers: u32) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d { width, height, depth_or_array_layers: layers },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[]
+        })
+    }
+
+    fn bind_group(device: &Device, layout: &BindGroupLayout, sampler: &Sampler, texture: &Texture) -> BindGroup {
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            array_layer_count: Some(texture.depth_or_array_layers()),
+            ..Default::default()
+        });
+
+        device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler)
+                }
+            ]
+        })
+    }
+}
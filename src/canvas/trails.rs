@@ -0,0 +1,242 @@
+use bytemuck::{cast_slice, Pod, Zeroable};
+use log::info;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, CompareFunction, DepthStencilState, Device, FragmentState, IndexFormat, PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderStages, TextureFormat, VertexState};
+use crate::trajectory::Frame;
+use super::planets::{PlanetInstance, Planets};
+
+//Positions kept per body, fewer if there are so many bodies that the ring would exceed MAX_VERTICES
+const TRAIL_LENGTH: usize = 512;
+const MAX_VERTICES: usize = 1 << 20;
+//Position w of a body that doesn't exist in a row, far enough below zero that its segments are discarded
+const ABSENT: f32 = -1e9;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Parameters {
+    head: u32,
+    rows: u32,
+    bodies: u32,
+    padding: u32,
+}
+
+//Fading lines through the last positions of every body
+pub(super) struct Trails {
+    pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    ring: Option<Ring>,
+    enabled: bool,
+}
+
+//Rows of planet instances, one row per appended frame with the bodies in the order of their ids.
+//The index buffer connects every row to the next one, block by block, so skipping the block
+//from the newest row to the oldest one draws all trails with at most two calls.
+struct Ring {
+    buffer: Buffer,
+    index_buffer: Buffer,
+    parameter_buffer: Buffer,
+    bind_group: BindGroup,
+    bodies: usize,
+    rows: usize,
+    //Row written last and the number of rows written so far
+    head: usize,
+    filled: usize,
+    //Time of the last appended frame, playback standing still doesn't shorten the trails
+    time: Option<f64>,
+}
+
+impl Trails {
+    pub(super) fn new(device: &Device, camera_layout: &BindGroupLayout, format: TextureFormat, depth_stencil: DepthStencilState) -> Trails {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                count: None,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None
+                }
+            }]
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_layout, &layout],
+            push_constant_ranges: &[]
+        });
+
+        let shader_module = device.create_shader_module(include_wgsl!("trails.wgsl"));
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                buffers: &[PlanetInstance::vertex_desc()],
+                module: &shader_module,
+                entry_point: Some("vertex"),
+                compilation_options: Default::default()
+            },
+            fragment: Some(FragmentState {
+                module: &shader_module,
+                entry_point: Some("fragment"),
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL
+                })]
+            }),
+            cache: None,
+            //The faded ends mustn't hide what is behind them
+            depth_stencil: Some(DepthStencilState {
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Less,
+                ..depth_stencil
+            }),
+            multisample: Default::default(),
+            multiview: None,
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::LineList,
+                ..Default::default()
+            }
+        });
+
+        Trails {
+            pipeline,
+            layout,
+            ring: None,
+            enabled: true
+        }
+    }
+
+    pub(super) fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        info!("Trails: {}", if self.enabled { "on" } else { "off" });
+    }
+
+    //Starts empty trails for the given number of bodies
+    pub(super) fn reset(&mut self, device: &Device, bodies: usize) {
+        self.ring = (bodies > 0).then(|| Ring::new(device, &self.layout, bodies));
+    }
+
+    //Drops the trails drawn so far, for playback that jumps
+    pub(super) fn clear(&mut self) {
+        if let Some(ring) = &mut self.ring {
+            ring.head = ring.rows - 1;
+            ring.filled = 0;
+            ring.time = None;
+        }
+    }
+
+    //Appends the bodies of a frame, the instances of bodies missing from it are marked absent
+    pub(super) fn push(&mut self, queue: &Queue, frame: &Frame, planets: &Planets) {
+        let Some(ring) = &mut self.ring else { return };
+        if frame.time.is_some() && frame.time == ring.time {
+            return;
+        }
+        ring.time = frame.time;
+
+        let mut row = vec![PlanetInstance::new([0.0, 0.0, 0.0, ABSENT], [0.0; 4]); ring.bodies];
+        for (entry, position) in frame.positions.iter().enumerate() {
+            let body = frame.body(entry);
+            if let Some(instance) = row.get_mut(body) {
+                let [x, y, z] = position.map(|value| (value * planets.scale()) as f32);
+                *instance = PlanetInstance::new([x, y, z, 1.0], planets.color(body));
+            }
+        }
+
+        let offset = ring.advance();
+        queue.write_buffer(&ring.buffer, offset, cast_slice(&row));
+    }
+
+    //Appends the planet instances as they are, for the GPU simulation that never hands its positions to the CPU
+    pub(super) fn copy(&mut self, command_encoder: &mut CommandEncoder, planets: &Planets) {
+        let Some(ring) = &mut self.ring else { return };
+        let offset = ring.advance();
+        command_encoder.copy_buffer_to_buffer(&planets.buffer, 0, &ring.buffer, offset, ring.row_size());
+    }
+
+    pub(super) fn draw(&self, queue: &Queue, render_pass: &mut RenderPass, camera: &BindGroup) {
+        let Some(ring) = self.ring.as_ref().filter(|ring| self.enabled && ring.filled > 1) else { return };
+        queue.write_buffer(&ring.parameter_buffer, 0, cast_slice(&[Parameters {
+            head: ring.head as u32,
+            rows: ring.rows as u32,
+            bodies: ring.bodies as u32,
+            padding: 0
+        }]));
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera, &[]);
+        render_pass.set_bind_group(1, &ring.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, ring.buffer.slice(..));
+        render_pass.set_index_buffer(ring.index_buffer.slice(..), IndexFormat::Uint32);
+
+        //Block i connects row i with the next one, the block leaving the head would connect the newest row with the oldest
+        let block = ring.bodies as u32 * 2;
+        render_pass.draw_indexed(0..ring.head as u32 * block, 0, 0..1);
+        if ring.filled == ring.rows {
+            render_pass.draw_indexed((ring.head as u32 + 1) * block..ring.rows as u32 * block, 0, 0..1);
+        }
+    }
+}
+
+impl Ring {
+    fn new(device: &Device, layout: &BindGroupLayout, bodies: usize) -> Ring {
+        let rows = TRAIL_LENGTH.min(MAX_VERTICES / bodies).max(2);
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: (rows * bodies * size_of::<PlanetInstance>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let indices = (0..rows)
+            .flat_map(|row| (0..bodies).flat_map(move |body| [row * bodies + body, (row + 1) % rows * bodies + body]))
+            .map(|index| index as u32)
+            .collect::<Vec<_>>();
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(&indices),
+            usage: BufferUsages::INDEX
+        });
+
+        let parameter_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: cast_slice(&[Parameters::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: parameter_buffer.as_entire_binding()
+            }]
+        });
+
+        Ring {
+            buffer,
+            index_buffer,
+            parameter_buffer,
+            bind_group,
+            bodies,
+            rows,
+            head: rows - 1,
+            filled: 0,
+            time: None
+        }
+    }
+
+    fn row_size(&self) -> u64 {
+        (self.bodies * size_of::<PlanetInstance>()) as u64
+    }
+
+    //Moves the head to the next row and returns where it starts in the buffer
+    fn advance(&mut self) -> u64 {
+        self.head = (self.head + 1) % self.rows;
+        self.filled = (self.filled + 1).min(self.rows);
+        self.head as u64 * self.row_size()
+    }
+}
@@ -0,0 +1,56 @@
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> trail: Trail;
+
+struct Camera {
+    projection: mat4x4<f32>,
+    position: vec4<f32>
+}
+
+struct Trail {
+    head: u32,
+    rows: u32,
+    bodies: u32,
+    padding: u32,
+}
+
+struct VertexInput {
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) position: vec4<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) presence: f32,
+}
+
+struct FragmentInput {
+    @location(0) color: vec4<f32>,
+    @location(1) presence: f32,
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>
+}
+
+@vertex fn vertex(input: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    let row = input.vertex_index / trail.bodies;
+    let age = (trail.head + trail.rows - row) % trail.rows;
+    output.position = camera.projection * vec4<f32>(input.position.xyz, 1.0);
+    output.color = vec4<f32>(input.color.xyz, 1.0 - f32(age) / f32(trail.rows));
+    output.presence = input.position.w;
+    return output;
+}
+
+@fragment fn fragment(input: FragmentInput) -> FragmentOutput {
+    //Segments towards a position where the body didn't exist
+    if input.presence < 0.0 {
+        discard;
+    }
+
+    var output: FragmentOutput;
+    output.color = input.color;
+    return output;
+}
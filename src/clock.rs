@@ -0,0 +1,125 @@
+#[cfg(target_arch = "wasm32")]
+use web_time::{Duration, Instant};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+//Drives playback, every call to tick returns the time that passed since the previous call
+pub trait Clock {
+    fn tick(&mut self) -> Duration;
+}
+
+pub struct RealTimeClock {
+    last_tick: Instant
+}
+
+//Advances by the same step on every tick, so frame N of an export always shows the same time
+pub struct FixedStepClock {
+    step: Duration
+}
+
+impl RealTimeClock {
+    pub fn new() -> RealTimeClock {
+        RealTimeClock { last_tick: Instant::now() }
+    }
+}
+
+impl Default for RealTimeClock {
+    fn default() -> RealTimeClock {
+        RealTimeClock::new()
+    }
+}
+
+impl Clock for RealTimeClock {
+    fn tick(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+        elapsed
+    }
+}
+
+impl FixedStepClock {
+    pub fn new(step: Duration) -> FixedStepClock {
+        FixedStepClock { step }
+    }
+}
+
+impl Clock for FixedStepClock {
+    fn tick(&mut self) -> Duration {
+        self.step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::System;
+    use crate::trajectory::{ParseMode, Trajectory};
+
+    //One body moving along x with its time, one unit per frame, played back at one unit per tick
+    fn system() -> System {
+        let trajectory = Trajectory::parse("# time\n0 0 0 0\n1 1 0 0\n2 2 0 0\n3 3 0 0\n", ParseMode::Strict).unwrap();
+        let mut system = System::new(trajectory);
+        system.set_clock(Box::new(FixedStepClock::new(Duration::from_millis(100))));
+        system
+    }
+
+    fn ticks(system: &mut System, count: usize) -> f64 {
+        (0..count).map(|_| system.step().positions[0][0]).last().unwrap_or_default()
+    }
+
+    #[test]
+    fn fixed_step_advances_the_same_time_every_tick() {
+        let mut system = system();
+        assert_eq!(ticks(&mut system, 2), 2.0);
+        assert_eq!(system.time(), 2.0);
+    }
+
+    #[test]
+    fn fixed_step_plays_backwards_when_reversed() {
+        let mut system = system();
+        system.jump_to_end();
+        system.reverse();
+        assert_eq!(ticks(&mut system, 2), 1.0);
+        assert_eq!(system.time(), 1.0);
+    }
+
+    #[test]
+    fn fixed_step_loops_after_holding_the_last_frame() {
+        let mut system = system();
+        assert_eq!(ticks(&mut system, 3), 3.0);
+        assert_eq!(ticks(&mut system, 1), 0.0);
+        assert_eq!(ticks(&mut system, 1), 1.0);
+    }
+
+    #[test]
+    fn fixed_step_counts_a_jump_when_starting_over() {
+        let mut system = system();
+        ticks(&mut system, 3);
+        system.step_backward();
+        assert_eq!(system.jumps(), 0);
+        ticks(&mut system, 1);
+        system.toggle_pause();
+        ticks(&mut system, 2);
+        assert_eq!(system.jumps(), 1);
+        system.jump_to_end();
+        assert_eq!(system.jumps(), 2);
+    }
+
+    #[test]
+    fn fixed_step_turns_around_in_ping_pong_mode() {
+        let mut system = system();
+        system.next_end_mode();
+        assert_eq!(ticks(&mut system, 4), 2.0);
+        assert_eq!(ticks(&mut system, 1), 1.0);
+    }
+
+    #[test]
+    fn fixed_step_holds_the_last_frame() {
+        let mut system = system();
+        system.next_end_mode();
+        system.next_end_mode();
+        assert_eq!(ticks(&mut system, 5), 3.0);
+        assert_eq!(system.time(), 3.0);
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use winit::dpi::{PhysicalPosition, PhysicalSize};
 use winit::event::{ElementState, MouseButton, WindowEvent};
 use winit::application::ApplicationHandler;
@@ -6,17 +8,23 @@ use winit::event_loop::{ActiveEventLoop, EventLoopProxy};
 use winit::keyboard::{Key, NamedKey};
 use winit::window::{WindowAttributes, WindowId, Window};
 use crate::canvas::Canvas;
+use crate::clock::FixedStepClock;
 use crate::system::System;
+use crate::trajectory::Trajectory;
 
-pub enum Application {
+pub(crate) enum Application {
     Initializing(Arc<Window>),
-    Uninitialized(WindowAttributes, EventLoopProxy<Canvas>),
-    Initialized(ApplicationState)
+    Uninitialized(Box<WindowAttributes>, EventLoopProxy<Canvas>),
+    Initialized(Box<ApplicationState>)
 }
 
-pub(super) static CONTENT: Mutex<Option<String>> = Mutex::new(None);
+pub static CONTENT: Mutex<Option<Trajectory>> = Mutex::new(None);
+pub static FIXED_STEP: Mutex<Option<Duration>> = Mutex::new(None);
+pub static ENCOUNTER_DISTANCE: Mutex<Option<f64>> = Mutex::new(None);
+//Encoded images by file name, a body uses the one its texture attribute names or the one named after it
+pub static TEXTURES: Mutex<BTreeMap<String, Vec<u8>>> = Mutex::new(BTreeMap::new());
 
-pub struct ApplicationState {
+pub(crate) struct ApplicationState {
     window: Arc<Window>,
     canvas: Canvas,
     last_position: PhysicalPosition<f64>,
@@ -24,13 +32,16 @@ pub struct ApplicationState {
     rotating: bool,
     zoom: f32,
     pitch: f32,
-    yaw: f32
+    yaw: f32,
+    //Title the window had before it showed orbital elements, and the elements shown last
+    title: String,
+    status: Option<String>
 }
 
 impl ApplicationHandler<Canvas> for Application {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if let Application::Uninitialized(window_attributes, proxy) = self {
-            let window = Arc::new(event_loop.create_window(window_attributes.clone()).unwrap());
+            let window = Arc::new(event_loop.create_window(window_attributes.as_ref().clone()).unwrap());
             let proxy = proxy.clone();
             *self = Application::Initializing(window.clone());
             Application::init(proxy, window);
@@ -58,6 +69,30 @@ impl ApplicationHandler<Canvas> for Application {
                 match event.logical_key {
                     Key::Named(NamedKey::Space) => state.system.speed_up(),   
                     Key::Named(NamedKey::Backspace) => state.system.slow_down(),
+                    Key::Named(NamedKey::ArrowRight) => state.system.step_forward(),
+                    Key::Named(NamedKey::ArrowLeft) => state.system.step_backward(),
+                    Key::Named(NamedKey::Home) => state.system.jump_to_start(),
+                    Key::Named(NamedKey::End) => state.system.jump_to_end(),
+                    Key::Character(ref character) => match character.as_str() {
+                        "p" => state.system.toggle_pause(),
+                        "r" => state.system.reverse(),
+                        "i" => state.system.next_interpolation(),
+                        "l" => state.system.next_end_mode(),
+                        "h" => state.system.log_step_history(),
+                        "e" => state.system.log_diagnostics(),
+                        "b" => state.system.select_next_body(),
+                        "c" => state.system.select_next_primary(),
+                        "t" => state.canvas.toggle_trails(),
+                        "o" => state.canvas.toggle_paths(),
+                        "z" => state.canvas.next_size_mode(),
+                        "+" => state.canvas.exaggerate(2.0),
+                        "-" => state.canvas.exaggerate(0.5),
+                        "n" => state.system.jump_to_next_event(),
+                        "N" => state.system.jump_to_previous_event(),
+                        digit => if let Ok(digit) = digit.parse::<u8>() {
+                            state.system.seek_fraction(digit as f64 / 10.0);
+                        }
+                    },
                     _ => ()
                 }
             },
@@ -72,8 +107,8 @@ impl ApplicationHandler<Canvas> for Application {
                     state.window.request_redraw();
                 } 
 
-                if let Some(content) = CONTENT.lock().unwrap().take() {
-                    state.system = System::new(&state.canvas.device, content);
+                if let Some(trajectory) = CONTENT.lock().unwrap().take() {
+                    state.system = load(&mut state.canvas, trajectory);
                     state.window.request_redraw();
                 }
 
@@ -82,24 +117,42 @@ impl ApplicationHandler<Canvas> for Application {
             WindowEvent::RedrawRequested => {
                 let PhysicalSize { width, height } = state.window.inner_size();
                 state.canvas.update(&mut state.system, width, height, state.yaw, state.pitch, state.zoom);
+                let status = state.system.orbit_status();
+                if status != state.status {
+                    state.window.set_title(status.as_deref().unwrap_or(&state.title));
+                    state.status = status;
+                }
                 state.window.request_redraw();
             }
             _ => ()
         }
     }
 
-    fn user_event(&mut self, _: &ActiveEventLoop, canvas: Canvas) {
+    fn user_event(&mut self, _: &ActiveEventLoop, mut canvas: Canvas) {
         if let Application::Initializing(window) = self {
-            *self = Application::Initialized(ApplicationState {
+            let system = load(&mut canvas, CONTENT.lock().unwrap().take().unwrap_or_default());
+            *self = Application::Initialized(Box::new(ApplicationState {
                 window: { window.request_redraw(); window.clone() },
                 last_position: PhysicalPosition::new(0.0, 0.0),
-                system: System::new(&canvas.device, "".to_string()),
+                system,
                 rotating: false,
                 zoom: 1.0,
                 pitch: -30.0,
                 yaw: 0.0,
+                title: window.title(),
+                status: None,
                 canvas
-            })
+            }))
         }
     }
+}
+
+fn load(canvas: &mut Canvas, trajectory: Trajectory) -> System {
+    let mut system = System::new(trajectory);
+    if let Some(step) = *FIXED_STEP.lock().unwrap() {
+        system.set_clock(Box::new(FixedStepClock::new(step)));
+    }
+    system.detect_events(*ENCOUNTER_DISTANCE.lock().unwrap());
+    canvas.load(&mut system, &TEXTURES.lock().unwrap());
+    system
 }
\ No newline at end of file
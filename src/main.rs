@@ -1,18 +1,15 @@
 #![cfg_attr(target_arch = "wasm32", no_main)]
 #![cfg(not(target_arch = "wasm32"))]
-use std::sync::Arc;
-use log::{error, LevelFilter};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+use log::{error, info, LevelFilter};
 use simplelog::{Config, SimpleLogger};
-use winit::dpi::PhysicalSize;
-use winit::event_loop::{EventLoop, EventLoopProxy};
-use winit::window::{Window, WindowAttributes};
-use pollster::FutureExt;
-use entry::Application;
-use canvas::Canvas;
-
-mod entry;
-mod canvas;
-mod system;
+use wasm::entry;
+use wasm::physics::{self, ForceEvaluator, Integrator, Preset, SimulationSettings};
+use wasm::trajectory::{CsvColumns, ParseMode, Precision, Span, Trajectory};
 
 fn main() {
     SimpleLogger::init(LevelFilter::Info, Config::default()).ok();
@@ -20,23 +17,246 @@ fn main() {
         error!("{panic_info}");
     }));
 
-    let event_loop = EventLoop::with_user_event().build().unwrap();
-    let mut application = Application::new(event_loop.create_proxy());
-    event_loop.run_app(&mut application).unwrap();
-}
+    let mut mode = ParseMode::Strict;
+    let mut columns = CsvColumns::default();
+    let mut precision = Precision::Double;
+    let mut convert = None;
+    let mut simulate = None;
+    let mut integrator = None;
+    let mut tolerance = None;
+    let mut opening_angle = None;
+    let mut benchmark = None;
+    let mut gpu = false;
+    let mut diagnostics = false;
+    let mut osculating = None;
+    let mut events = None;
+    let mut preset = None;
+    let mut seed = 0;
+    let mut count = None;
+    let mut export = None;
+    let mut span = None;
+    let mut textures = Vec::new();
+    let mut path = None;
+    let mut arguments = std::env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--lenient" => mode = ParseMode::Lenient,
+            "--columns" => match arguments.next().as_deref().and_then(CsvColumns::parse) {
+                Some(names) => columns = names,
+                None => error!("--columns expects time,body,x,y,z[,vx,vy,vz]")
+            },
+            "--convert" => convert = arguments.next(),
+            "--f32" => precision = Precision::Single,
+            "--fps" => match arguments.next().and_then(|fps| fps.parse::<f64>().ok()).filter(|&fps| fps > 0.0) {
+                Some(fps) => *entry::FIXED_STEP.lock().unwrap() = Some(Duration::from_secs_f64(1.0 / fps)),
+                None => error!("--fps expects a positive number of frames per second")
+            },
+            "--simulate" => match arguments.next().and_then(|step| step.parse::<f64>().ok()).filter(|&step| step > 0.0) {
+                Some(step) => simulate = Some(SimulationSettings::new(step)),
+                None => error!("--simulate expects a positive step in seconds")
+            },
+            "--integrator" => match arguments.next().as_deref().and_then(Integrator::parse) {
+                Some(name) => integrator = Some(name),
+                None => error!("--integrator expects leapfrog or rk45")
+            },
+            "--tolerance" => match arguments.next().and_then(|value| value.parse::<f64>().ok()).filter(|&value| value > 0.0) {
+                Some(value) => tolerance = Some(value),
+                None => error!("--tolerance expects a positive relative error")
+            },
+            "--opening-angle" => match arguments.next().and_then(|value| value.parse::<f64>().ok()).filter(|&value| value > 0.0) {
+                Some(value) => opening_angle = Some(value),
+                None => error!("--opening-angle expects a positive angle in radians")
+            },
+            "--gpu" => gpu = true,
+            "--diagnostics" => diagnostics = true,
+            "--osculating" => match arguments.next() {
+                Some(bodies) => osculating = Some(bodies),
+                None => error!("--osculating expects a body name and optionally the name of its primary, separated by a comma")
+            },
+            "--preset" => match arguments.next().as_deref().and_then(Preset::parse) {
+                Some(name) => preset = Some(name),
+                None => error!("--preset expects solar-system, figure-eight, disk, plummer or circumbinary")
+            },
+            "--seed" => match arguments.next().and_then(|value| value.parse::<u64>().ok()) {
+                Some(value) => seed = value,
+                None => error!("--seed expects a whole number")
+            },
+            "--count" => match arguments.next().and_then(|value| value.parse::<usize>().ok()) {
+                Some(value) => count = Some(value),
+                None => error!("--count expects a number of bodies")
+            },
+            "--export" => export = arguments.next(),
+            "--events" => events = arguments.next(),
+            "--encounter" => match arguments.next().and_then(|value| value.parse::<f64>().ok()).filter(|&value| value > 0.0) {
+                Some(distance) => *entry::ENCOUNTER_DISTANCE.lock().unwrap() = Some(distance),
+                None => error!("--encounter expects a positive distance")
+            },
+            "--span" => match arguments.next().as_deref().and_then(Span::parse) {
+                Some(value) => span = Some(value),
+                None => error!("--span expects start,end,step in seconds with a positive step and at most {} frames", Span::MAX_FRAMES)
+            },
+            "--texture" => match arguments.next() {
+                Some(image) => textures.push(image),
+                None => error!("--texture expects a PNG or JPEG image")
+            },
+            "--benchmark-gravity" => match arguments.next().and_then(|count| count.parse::<usize>().ok()) {
+                Some(count) => benchmark = Some(count),
+                None => error!("--benchmark-gravity expects a number of bodies")
+            },
+            _ => path = Some(argument)
+        }
+    }
 
-impl Application {
-    fn new(proxy: EventLoopProxy<Canvas>) -> Application {
-        let window_attributes = WindowAttributes::default()
-            .with_title("WebGPU User Interface")
-            .with_inner_size(PhysicalSize::new(800, 600));
+    if let Some(count) = benchmark {
+        let evaluator = ForceEvaluator::BarnesHut { opening_angle: opening_angle.unwrap_or(0.5) };
+        let comparison = physics::compare(evaluator, count, 1);
+        info!("{count} bodies, direct summation {:?}, {evaluator:?} {:?}", comparison.direct, comparison.evaluator);
+        info!("relative error median {:e}, max {:e}", comparison.median_error, comparison.max_error);
+        return;
+    }
 
-        Application::Uninitialized(window_attributes, proxy)
+    let directory = path.as_deref().and_then(|path| Path::new(path).parent()).map(Path::to_path_buf).unwrap_or_default();
+    let loaded = match (preset, path) {
+        (Some(preset), _) => {
+            info!("generated {preset} with seed {seed}");
+            Some(Ok(preset.generate(seed, count)))
+        }
+        (None, Some(path)) => Some(load(&path, &columns, span, mode).map_err(|err| format!("{path}: {err}"))),
+        (None, None) => None
+    };
+
+    match loaded {
+        Some(Ok(mut trajectory)) => {
+            trajectory.simulation = simulate.or(trajectory.simulation);
+            if let Some(settings) = &mut trajectory.simulation {
+                settings.integrator = integrator.unwrap_or(settings.integrator);
+                settings.tolerance = tolerance.unwrap_or(settings.tolerance);
+                settings.gpu |= gpu;
+                if let Some(opening_angle) = opening_angle {
+                    settings.force = ForceEvaluator::BarnesHut { opening_angle };
+                }
+            }
+            //Images the bodies refer to are next to the data, the ones given on the command line are found by their file name
+            let referenced = trajectory.bodies.iter()
+                .filter_map(|body| body.texture.clone())
+                .map(|name| (directory.join(&name), name));
+            let given = textures.iter()
+                .filter_map(|image| Some((image.into(), Path::new(image).file_name()?.to_string_lossy().into_owned())));
+            for (file, name) in referenced.chain(given) {
+                match std::fs::read(&file) {
+                    Ok(content) => { entry::TEXTURES.lock().unwrap().insert(name, content); }
+                    Err(err) => error!("{}: {err}", file.display())
+                }
+            }
+            *entry::CONTENT.lock().unwrap() = Some(trajectory);
+        }
+        Some(Err(err)) => error!("{err}"),
+        None => ()
     }
 
-    fn init(proxy: EventLoopProxy<Canvas>, window: Arc<Window>) {
-        let size = window.inner_size();
-        let context = Canvas::new(window, size).block_on();
-        proxy.send_event(context).ok();
+    //Prints the conserved quantities of every frame as CSV and the drift over the run without opening a window
+    if diagnostics {
+        let Some(trajectory) = entry::CONTENT.lock().unwrap().take() else { return };
+        let frames = physics::analyze(&trajectory);
+        println!("t,kinetic,potential,energy,px,py,pz,lx,ly,lz,cx,cy,cz");
+        for frame in &frames {
+            let [px, py, pz] = frame.momentum;
+            let [lx, ly, lz] = frame.angular_momentum;
+            let [cx, cy, cz] = frame.barycentre;
+            //Without timestamps or velocities the kinetic energy and the total are left empty
+            let kinetic = frame.kinetic.map_or(String::new(), |kinetic| kinetic.to_string());
+            let energy = frame.energy().map_or(String::new(), |energy| energy.to_string());
+            println!("{},{kinetic},{},{energy},{px},{py},{pz},{lx},{ly},{lz},{cx},{cy},{cz}", frame.time, frame.potential);
+        }
+
+        let drift = physics::drift(&frames);
+        let energy = drift.energy.map_or(String::from("unknown"), |energy| format!("{energy:e}"));
+        info!("relative drift over {} frames: energy {energy}, momentum {:e}, angular momentum {:e}, barycentre moved {:e}",
+            frames.len(), drift.momentum, drift.angular_momentum, drift.barycentre);
+        return;
     }
-}
\ No newline at end of file
+
+    //Prints the osculating elements of a body at every frame as CSV, angles in degrees
+    if let Some(bodies) = osculating {
+        let Some(trajectory) = entry::CONTENT.lock().unwrap().take() else { return };
+        let find = |name: &str| trajectory.bodies.iter().position(|body| body.name.as_deref() == Some(name));
+        let (name, primary_name) = bodies.split_once(',').map_or((bodies.as_str(), None), |(name, primary)| (name, Some(primary)));
+        let Some(body) = find(name) else {
+            error!("--osculating: unknown body {name}");
+            return;
+        };
+        let primary = match primary_name {
+            Some(primary_name) => find(primary_name),
+            None => physics::primary(&trajectory, body)
+        };
+        let Some(primary) = primary else {
+            error!("--osculating: no primary for {name}");
+            return;
+        };
+
+        println!("t,a,e,i,node,periapsis,true_anomaly,period");
+        for index in 0..trajectory.len() {
+            let Some(osculating) = physics::osculating(&trajectory, index, body, primary) else { continue };
+            let elements = osculating.elements;
+            println!("{},{},{},{},{},{},{},{}", osculating.time, elements.semi_major_axis, elements.eccentricity,
+                elements.inclination.to_degrees(), elements.ascending_node.to_degrees(), elements.argument_of_periapsis.to_degrees(),
+                osculating.true_anomaly.to_degrees(), osculating.period.map_or(String::new(), |period| period.to_string()));
+        }
+        return;
+    }
+
+    //Writes the event log of the loaded frames as CSV
+    if let Some(output) = events {
+        let Some(mut trajectory) = entry::CONTENT.lock().unwrap().take() else { return };
+        physics::Detector::new(*entry::ENCOUNTER_DISTANCE.lock().unwrap()).scan(&mut trajectory);
+        let result = File::create(&output)
+            .and_then(|file| trajectory.write_events(&mut BufWriter::new(file)));
+
+        match result {
+            Ok(()) => info!("wrote {} events to {output}", trajectory.events.len()),
+            Err(err) => error!("{output}: {err}")
+        }
+        return;
+    }
+
+    if let Some(output) = export {
+        let Some(trajectory) = entry::CONTENT.lock().unwrap().take() else { return };
+        let result = File::create(&output)
+            .and_then(|file| trajectory.write_text(&mut BufWriter::new(file)));
+
+        match result {
+            Ok(()) => info!("wrote {} frames to {output}", trajectory.len()),
+            Err(err) => error!("{output}: {err}")
+        }
+        return;
+    }
+
+    if let Some(output) = convert {
+        let Some(trajectory) = entry::CONTENT.lock().unwrap().take() else { return };
+        let result = File::create(&output)
+            .and_then(|file| trajectory.write_binary(&mut BufWriter::new(file), precision));
+
+        match result {
+            Ok(()) => info!("wrote {} frames to {output}", trajectory.len()),
+            Err(err) => error!("{output}: {err}")
+        }
+        return;
+    }
+
+    wasm::run();
+}
+
+fn load(path: &str, columns: &CsvColumns, span: Option<Span>, mode: ParseMode) -> Result<Trajectory, Box<dyn Error>> {
+    if path.ends_with(".pvt") {
+        return Ok(Trajectory::open_binary(path)?);
+    }
+
+    let content = std::fs::read_to_string(path)?;
+    if path.ends_with(".csv") {
+        Ok(Trajectory::parse_csv(&content, columns, mode)?)
+    } else if path.ends_with(".kep") {
+        Ok(Trajectory::parse_elements(&content, span, mode)?)
+    } else {
+        Ok(Trajectory::parse(&content, mode)?)
+    }
+}
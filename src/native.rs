@@ -0,0 +1,30 @@
+use std::sync::Arc;
+use winit::dpi::PhysicalSize;
+use winit::event_loop::{EventLoop, EventLoopProxy};
+use winit::window::{Window, WindowAttributes};
+use pollster::FutureExt;
+use crate::canvas::Canvas;
+use crate::entry::Application;
+
+//Opens the window and shows the trajectory put into entry::CONTENT until it is closed
+pub fn run() {
+    let event_loop = EventLoop::with_user_event().build().unwrap();
+    let mut application = Application::new(event_loop.create_proxy());
+    event_loop.run_app(&mut application).unwrap();
+}
+
+impl Application {
+    fn new(proxy: EventLoopProxy<Canvas>) -> Application {
+        let window_attributes = WindowAttributes::default()
+            .with_title("WebGPU User Interface")
+            .with_inner_size(PhysicalSize::new(800, 600));
+
+        Application::Uninitialized(Box::new(window_attributes), proxy)
+    }
+
+    pub(super) fn init(proxy: EventLoopProxy<Canvas>, window: Arc<Window>) {
+        let size = window.inner_size();
+        let context = Canvas::new(window, size).block_on();
+        proxy.send_event(context).ok();
+    }
+}
@@ -0,0 +1,135 @@
+use std::ops::Range;
+
+//Bodies per leaf and depth limit, the limit keeps coincident bodies from splitting forever
+const LEAF_SIZE: usize = 4;
+const MAX_DEPTH: usize = 32;
+const NONE: usize = usize::MAX;
+
+struct Node {
+    center: [f64; 3],
+    half_size: f64,
+    mass: f64,
+    center_of_mass: [f64; 3],
+    children: [usize; 8],
+    //Range into the body order of the tree, empty for inner nodes
+    bodies: Range<usize>
+}
+
+//Octree over the bodies, every node knows the total mass and center of mass of the bodies below it
+pub(super) struct Octree {
+    nodes: Vec<Node>,
+    order: Vec<usize>
+}
+
+impl Octree {
+    pub(super) fn new(positions: &[[f64; 3]], masses: &[f64]) -> Octree {
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for position in positions {
+            for ((min, max), &value) in min.iter_mut().zip(max.iter_mut()).zip(position) {
+                *min = min.min(value);
+                *max = max.max(value);
+            }
+        }
+
+        let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) * 0.5);
+        let half_size = (0..3).map(|axis| max[axis] - min[axis]).fold(0.0, f64::max) * 0.5;
+        let mut tree = Octree { nodes: Vec::new(), order: (0..positions.len()).collect() };
+        if !positions.is_empty() {
+            tree.build(positions, masses, 0..positions.len(), center, half_size, 0);
+        }
+        tree
+    }
+
+    fn build(&mut self, positions: &[[f64; 3]], masses: &[f64], bodies: Range<usize>, center: [f64; 3], half_size: f64, depth: usize) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node { center, half_size, mass: 0.0, center_of_mass: [0.0; 3], children: [NONE; 8], bodies: bodies.clone() });
+
+        let mut mass = 0.0;
+        let mut weighted = [0.0; 3];
+        if bodies.len() <= LEAF_SIZE || depth == MAX_DEPTH {
+            for &body in &self.order[bodies] {
+                mass += masses[body];
+                for (weighted, &value) in weighted.iter_mut().zip(&positions[body]) {
+                    *weighted += masses[body] * value;
+                }
+            }
+        } else {
+            let octant = |body: usize| (0..3).fold(0, |octant, axis| octant | ((positions[body][axis] >= center[axis]) as usize) << axis);
+            self.order[bodies.clone()].sort_unstable_by_key(|&body| octant(body));
+
+            let mut start = bodies.start;
+            for child in 0..8 {
+                let end = start + self.order[start..bodies.end].partition_point(|&body| octant(body) == child);
+                if end > start {
+                    let offset = [0, 1, 2].map(|axis| (if child >> axis & 1 == 1 { 0.5 } else { -0.5 }) * half_size);
+                    let child_center = [0, 1, 2].map(|axis| center[axis] + offset[axis]);
+                    let node = self.build(positions, masses, start..end, child_center, half_size * 0.5, depth + 1);
+                    let built = &self.nodes[node];
+                    mass += built.mass;
+                    for (weighted, &value) in weighted.iter_mut().zip(&built.center_of_mass) {
+                        *weighted += built.mass * value;
+                    }
+                    self.nodes[index].children[child] = node;
+                }
+                start = end;
+            }
+            self.nodes[index].bodies = bodies.start..bodies.start;
+        }
+
+        let node = &mut self.nodes[index];
+        node.mass = mass;
+        node.center_of_mass = if mass > 0.0 { weighted.map(|value| value / mass) } else { node.center };
+        index
+    }
+
+    //Nodes that appear smaller than the opening angle from the body are treated as a single point mass
+    pub(super) fn acceleration(&self, body: usize, positions: &[[f64; 3]], masses: &[f64], gravity: f64, softening: f64, opening_angle: f64) -> [f64; 3] {
+        let position = positions[body];
+        let softening = softening * softening;
+        let mut acceleration = [0.0; 3];
+        let mut add = |other: [f64; 3], mass: f64| {
+            let delta = [0, 1, 2].map(|axis| other[axis] - position[axis]);
+            let distance = delta.iter().map(|d| d * d).sum::<f64>() + softening;
+            if distance > 0.0 {
+                let factor = gravity * mass / (distance * distance.sqrt());
+                for (acceleration, &delta) in acceleration.iter_mut().zip(&delta) {
+                    *acceleration += factor * delta;
+                }
+            }
+        };
+
+        let mut stack = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.mass == 0.0 {
+                continue;
+            }
+
+            if !node.bodies.is_empty() {
+                for &other in &self.order[node.bodies.clone()] {
+                    if other != body {
+                        add(positions[other], masses[other]);
+                    }
+                }
+                continue;
+            }
+
+            let distance = (0..3).map(|axis| (node.center_of_mass[axis] - position[axis]).powi(2)).sum::<f64>();
+            let size = node.half_size * 2.0;
+            if size * size < opening_angle * opening_angle * distance && !node.contains(position) {
+                add(node.center_of_mass, node.mass);
+            } else {
+                stack.extend(node.children.iter().copied().filter(|&child| child != NONE));
+            }
+        }
+
+        acceleration
+    }
+}
+
+impl Node {
+    fn contains(&self, position: [f64; 3]) -> bool {
+        (0..3).all(|axis| (position[axis] - self.center[axis]).abs() <= self.half_size)
+    }
+}
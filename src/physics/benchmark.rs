@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+use super::gravity::ForceEvaluator;
+use super::random::Random;
+
+pub struct Comparison {
+    pub direct: Duration,
+    pub evaluator: Duration,
+    //Relative error of the acceleration vector, median and maximum over all bodies
+    pub median_error: f64,
+    pub max_error: f64
+}
+
+//Evaluates the forces of a uniform random sphere of equal masses with both the evaluator and direct summation
+pub fn compare(evaluator: ForceEvaluator, count: usize, seed: u64) -> Comparison {
+    let mut random = Random::new(seed);
+    let positions = (0..count).map(|_| random.in_unit_ball()).collect::<Vec<_>>();
+    let masses = vec![1.0 / count as f64; count];
+    let softening = 0.01;
+
+    let start = Instant::now();
+    let exact = ForceEvaluator::Direct.accelerations(&positions, &masses, 1.0, softening);
+    let direct = start.elapsed();
+
+    let start = Instant::now();
+    let approximate = evaluator.accelerations(&positions, &masses, 1.0, softening);
+    let evaluator = start.elapsed();
+
+    let mut errors = exact.iter().zip(&approximate)
+        .map(|(exact, approximate)| {
+            let difference = (0..3).map(|axis| (exact[axis] - approximate[axis]).powi(2)).sum::<f64>().sqrt();
+            let magnitude = exact.iter().map(|value| value * value).sum::<f64>().sqrt();
+            if magnitude > 0.0 { difference / magnitude } else { difference }
+        })
+        .collect::<Vec<_>>();
+    errors.sort_unstable_by(f64::total_cmp);
+
+    Comparison {
+        direct,
+        evaluator,
+        median_error: errors.get(errors.len() / 2).copied().unwrap_or_default(),
+        max_error: errors.last().copied().unwrap_or_default()
+    }
+}
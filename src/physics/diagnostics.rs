@@ -0,0 +1,138 @@
+use crate::trajectory::Trajectory;
+use super::GRAVITATIONAL_CONSTANT;
+
+//Conserved quantities of one frame, bodies without a mass don't contribute
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    //Index of the measured frame
+    pub frame: usize,
+    pub time: f64,
+    //None if the frame has neither velocities nor a time, velocities differenced over frame numbers have no unit
+    pub kinetic: Option<f64>,
+    pub potential: f64,
+    pub momentum: [f64; 3],
+    pub angular_momentum: [f64; 3],
+    pub barycentre: [f64; 3],
+    //Sums of the magnitudes, used to judge drift of quantities whose total is close to zero
+    momentum_scale: f64,
+    angular_momentum_scale: f64,
+}
+
+//Largest deviation from the first frame over the whole run, relative to its magnitude
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Drift {
+    //None if the kinetic energies are unknown
+    pub energy: Option<f64>,
+    pub momentum: f64,
+    pub angular_momentum: f64,
+    //Distance the barycentre moved, in length units
+    pub barycentre: f64,
+}
+
+impl Diagnostics {
+    pub fn energy(&self) -> Option<f64> {
+        self.kinetic.map(|kinetic| kinetic + self.potential)
+    }
+}
+
+impl Drift {
+    //Takes the deviation of another frame into account
+    pub fn add(&mut self, first: &Diagnostics, current: &Diagnostics) {
+        let relative = |difference: f64, scale: f64| if scale > 0.0 { difference / scale } else { difference };
+        if let Some((first, current)) = first.energy().zip(current.energy()) {
+            let energy = relative((current - first).abs(), first.abs());
+            self.energy = Some(self.energy.map_or(energy, |drift| drift.max(energy)));
+        }
+        self.momentum = self.momentum.max(relative(length(sub(current.momentum, first.momentum)), first.momentum_scale));
+        self.angular_momentum = self.angular_momentum.max(relative(
+            length(sub(current.angular_momentum, first.angular_momentum)),
+            first.angular_momentum_scale
+        ));
+        self.barycentre = self.barycentre.max(length(sub(current.barycentre, first.barycentre)));
+    }
+}
+
+//Measures every frame that can be measured
+pub fn analyze(trajectory: &Trajectory) -> Vec<Diagnostics> {
+    (0..trajectory.len()).filter_map(|index| diagnose(trajectory, index)).collect()
+}
+
+//Measures one frame, velocities are finite-differenced if the frame doesn't carry them.
+//The constant and softening of the simulation settings are used if there are any.
+pub fn diagnose(trajectory: &Trajectory, index: usize) -> Option<Diagnostics> {
+    let (gravity, softening) = trajectory.simulation
+        .map_or((GRAVITATIONAL_CONSTANT, 0.0), |settings| (settings.gravity, settings.softening));
+
+    let frame = trajectory.frame(index)?;
+    let velocities = trajectory.velocities(index)?;
+    let masses = (0..frame.positions.len())
+        .map(|entry| trajectory.bodies.get(frame.body(entry)).and_then(|body| body.mass).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let mut diagnostics = measure(&frame.positions, &velocities, &masses, gravity, softening);
+    diagnostics.frame = index;
+    diagnostics.time = trajectory.time(index)?;
+    if frame.velocities.is_none() && frame.time.is_none() {
+        diagnostics.kinetic = None;
+    }
+    Some(diagnostics)
+}
+
+pub fn measure(positions: &[[f64; 3]], velocities: &[[f64; 3]], masses: &[f64], gravity: f64, softening: f64) -> Diagnostics {
+    let mut diagnostics = Diagnostics::default();
+    let mut kinetic = 0.0;
+    let mut total_mass = 0.0;
+    for ((position, velocity), &mass) in positions.iter().zip(velocities).zip(masses) {
+        let speed = length(*velocity);
+        kinetic += 0.5 * mass * speed * speed;
+        let angular_momentum = cross(*position, *velocity);
+        for axis in 0..3 {
+            diagnostics.momentum[axis] += mass * velocity[axis];
+            diagnostics.angular_momentum[axis] += mass * angular_momentum[axis];
+            diagnostics.barycentre[axis] += mass * position[axis];
+        }
+        diagnostics.momentum_scale += mass * speed;
+        diagnostics.angular_momentum_scale += mass * length(*position) * speed;
+        total_mass += mass;
+    }
+
+    diagnostics.kinetic = Some(kinetic);
+    if total_mass > 0.0 {
+        diagnostics.barycentre = diagnostics.barycentre.map(|value| value / total_mass);
+    }
+
+    let softening = softening * softening;
+    for i in 0..positions.len() {
+        for j in i + 1..positions.len() {
+            let distance = (length(sub(positions[j], positions[i])).powi(2) + softening).sqrt();
+            if distance > 0.0 {
+                diagnostics.potential -= gravity * masses[i] * masses[j] / distance;
+            }
+        }
+    }
+
+    diagnostics
+}
+
+pub fn drift(diagnostics: &[Diagnostics]) -> Drift {
+    let Some(first) = diagnostics.first() else {
+        return Drift::default();
+    };
+
+    let mut drift = Drift::default();
+    for current in diagnostics {
+        drift.add(first, current);
+    }
+    drift
+}
+
+pub(super) fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(super) fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+pub(super) fn length(a: [f64; 3]) -> f64 {
+    (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt()
+}
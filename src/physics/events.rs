@@ -0,0 +1,180 @@
+use log::warn;
+use crate::trajectory::{Event, EventKind, Trajectory};
+use super::diagnostics::{length, sub};
+
+//Pairs are only compared up to this many bodies, the state of every pair is kept
+const MAX_PAIR_BODIES: usize = 500;
+//Pairs of bodies compared by one call to scan_to, so following the playback doesn't stall a frame
+const MAX_PAIRS_PER_SCAN: usize = 1 << 22;
+
+//Finds encounters, collisions, apsis passages and crossings of the reference plane frame by frame,
+//so a live simulation only has to scan the frames it added
+pub struct Detector {
+    //Separations below this distance are reported as encounters, None reports no encounters
+    encounter_distance: Option<f64>,
+    //Next frame to scan
+    next: usize,
+    //Time of the previous frame, events at an extremum happen there
+    previous_time: f64,
+    bodies: Vec<BodyState>,
+    pairs: Vec<PairState>,
+    warned: bool,
+}
+
+#[derive(Clone, Copy)]
+struct BodyState {
+    primary: Option<usize>,
+    //Distance to the primary in the frame before the previous one and in the previous one, NaN if unknown
+    distances: [f64; 2],
+    height: f64,
+}
+
+#[derive(Clone, Copy)]
+struct PairState {
+    distances: [f64; 2],
+    touching: bool,
+}
+
+impl Default for BodyState {
+    fn default() -> BodyState {
+        BodyState { primary: None, distances: [f64::NAN; 2], height: f64::NAN }
+    }
+}
+
+impl Default for PairState {
+    fn default() -> PairState {
+        PairState { distances: [f64::NAN; 2], touching: false }
+    }
+}
+
+impl Detector {
+    pub fn new(encounter_distance: Option<f64>) -> Detector {
+        Detector {
+            encounter_distance,
+            next: 0,
+            previous_time: 0.0,
+            bodies: Vec::new(),
+            pairs: Vec::new(),
+            warned: false
+        }
+    }
+
+    //Scans the frames not scanned yet and records their events in the trajectory
+    pub fn scan(&mut self, trajectory: &mut Trajectory) {
+        self.scan_frames(trajectory, trajectory.len());
+    }
+
+    //Scans on towards the given frame, only as many frames as fit into a small amount of work
+    pub fn scan_to(&mut self, trajectory: &mut Trajectory, end: usize) {
+        let bodies = self.bodies.len().max(trajectory.bodies.len()).clamp(1, MAX_PAIR_BODIES);
+        let frames = (MAX_PAIRS_PER_SCAN / (bodies * bodies)).max(1);
+        self.scan_frames(trajectory, end.min(self.next + frames));
+    }
+
+    fn scan_frames(&mut self, trajectory: &mut Trajectory, end: usize) {
+        let end = end.min(trajectory.len());
+        let mut events = Vec::new();
+        for index in self.next..end {
+            self.frame(trajectory, index, &mut events);
+        }
+        self.next = self.next.max(end);
+
+        for event in events {
+            trajectory.record(event);
+        }
+    }
+
+    //Continues with the given frame, the frames before it were scanned before the trajectory dropped some of them
+    pub fn skip_to(&mut self, index: usize) {
+        self.next = index;
+    }
+
+    fn frame(&mut self, trajectory: &Trajectory, index: usize, events: &mut Vec<Event>) {
+        let (Some(frame), Some(time)) = (trajectory.frame(index), trajectory.time(index)) else { return };
+        //Frames may hold bodies without a # body line, they get crossings and encounters but no apsides or collisions
+        let count = (0..frame.positions.len()).map(|entry| frame.body(entry) + 1).fold(trajectory.bodies.len(), usize::max);
+        if self.bodies.len() < count {
+            let known = self.bodies.len();
+            self.bodies.extend((known..count).map(|body| BodyState { primary: primary(trajectory, body), ..BodyState::default() }));
+        }
+        let count = self.bodies.len();
+
+        let mut entries = vec![None; count];
+        for entry in 0..frame.positions.len() {
+            if let Some(slot) = entries.get_mut(frame.body(entry)) {
+                *slot = Some(entry);
+            }
+        }
+
+        for body in 0..count {
+            let state = &mut self.bodies[body];
+            let Some(entry) = entries[body] else {
+                *state = BodyState { primary: state.primary, ..BodyState::default() };
+                continue;
+            };
+
+            let position = frame.positions[entry];
+            let height = position[2];
+            if state.height < 0.0 && height >= 0.0 || state.height > 0.0 && height <= 0.0 {
+                events.push(Event { time, kind: EventKind::PlaneCrossing { body, ascending: height >= 0.0 } });
+            }
+            state.height = height;
+
+            let distance = state.primary
+                .and_then(|primary| entries[primary])
+                .map_or(f64::NAN, |primary| length(sub(position, frame.positions[primary])));
+            let [before, previous] = state.distances;
+            if let Some(primary) = state.primary {
+                if previous < before && previous <= distance {
+                    events.push(Event { time: self.previous_time, kind: EventKind::Periapsis { body, primary, distance: previous } });
+                } else if previous > before && previous >= distance {
+                    events.push(Event { time: self.previous_time, kind: EventKind::Apoapsis { body, primary, distance: previous } });
+                }
+            }
+            state.distances = [previous, distance];
+        }
+
+        if count > MAX_PAIR_BODIES {
+            if !self.warned {
+                warn!("Encounters and collisions are only detected for up to {MAX_PAIR_BODIES} bodies");
+                self.warned = true;
+            }
+        } else {
+            if self.pairs.len() != count * count {
+                self.pairs = vec![PairState::default(); count * count];
+            }
+            for body in 0..count {
+                for other in body + 1..count {
+                    let state = &mut self.pairs[body * count + other];
+                    let (Some(body_entry), Some(other_entry)) = (entries[body], entries[other]) else {
+                        *state = PairState::default();
+                        continue;
+                    };
+
+                    let distance = length(sub(frame.positions[body_entry], frame.positions[other_entry]));
+                    let [before, previous] = state.distances;
+                    if self.encounter_distance.is_some_and(|threshold| previous < threshold) && previous < before && previous <= distance {
+                        events.push(Event { time: self.previous_time, kind: EventKind::Encounter { body, other, distance: previous } });
+                    }
+                    state.distances = [previous, distance];
+
+                    let radius = |body: usize| trajectory.bodies.get(body).and_then(|body| body.radius);
+                    let radii = radius(body).zip(radius(other));
+                    let touching = radii.is_some_and(|(radius, other_radius)| distance < radius + other_radius);
+                    if touching && !state.touching && !previous.is_nan() {
+                        events.push(Event { time, kind: EventKind::Collision { body, other } });
+                    }
+                    state.touching = touching;
+                }
+            }
+        }
+
+        self.previous_time = time;
+    }
+}
+
+//Apsides are measured around the most massive other body, if it is heavier than the body itself
+fn primary(trajectory: &Trajectory, body: usize) -> Option<usize> {
+    let mass = |body: usize| trajectory.bodies.get(body).and_then(|body| body.mass).unwrap_or_default();
+    super::primary(trajectory, body).filter(|&primary| mass(primary) > mass(body))
+}
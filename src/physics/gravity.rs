@@ -0,0 +1,47 @@
+use super::barnes_hut::Octree;
+
+//Strategy for evaluating the gravitational accelerations of all bodies
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ForceEvaluator {
+    //Exact pairwise summation, O(N²)
+    #[default]
+    Direct,
+    //Octree approximation in O(N log N), a smaller opening angle is slower but more accurate
+    BarnesHut { opening_angle: f64 }
+}
+
+impl ForceEvaluator {
+    //Softening keeps close encounters finite, bodies without mass feel gravity but don't pull
+    pub fn accelerations(&self, positions: &[[f64; 3]], masses: &[f64], gravity: f64, softening: f64) -> Vec<[f64; 3]> {
+        match *self {
+            ForceEvaluator::Direct => direct(positions, masses, gravity, softening),
+            ForceEvaluator::BarnesHut { opening_angle } => {
+                let tree = Octree::new(positions, masses);
+                (0..positions.len())
+                    .map(|body| tree.acceleration(body, positions, masses, gravity, softening, opening_angle))
+                    .collect()
+            }
+        }
+    }
+}
+
+fn direct(positions: &[[f64; 3]], masses: &[f64], gravity: f64, softening: f64) -> Vec<[f64; 3]> {
+    let mut accelerations = vec![[0.0; 3]; positions.len()];
+    let softening = softening * softening;
+    for i in 0..positions.len() {
+        for j in i + 1..positions.len() {
+            let delta = [0, 1, 2].map(|axis| positions[j][axis] - positions[i][axis]);
+            let distance = delta.iter().map(|d| d * d).sum::<f64>() + softening;
+            if distance == 0.0 {
+                continue;
+            }
+
+            let factor = gravity / (distance * distance.sqrt());
+            for axis in 0..3 {
+                accelerations[i][axis] += factor * masses[j] * delta[axis];
+                accelerations[j][axis] -= factor * masses[i] * delta[axis];
+            }
+        }
+    }
+    accelerations
+}
@@ -0,0 +1,135 @@
+use std::fmt::{Display, Formatter};
+use super::{SimulationSettings, State};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Integrator {
+    //Second order symplectic kick-drift-kick scheme with a fixed step
+    #[default]
+    Leapfrog,
+    //Embedded Runge-Kutta 5(4) pair that adapts the step to the tolerance, for close encounters
+    DormandPrince
+}
+
+impl Integrator {
+    pub fn parse(name: &str) -> Option<Integrator> {
+        match name {
+            "leapfrog" => Some(Integrator::Leapfrog),
+            "rk45" | "dopri5" => Some(Integrator::DormandPrince),
+            _ => None
+        }
+    }
+}
+
+impl Display for Integrator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Integrator::Leapfrog => write!(f, "leapfrog"),
+            Integrator::DormandPrince => write!(f, "rk45")
+        }
+    }
+}
+
+pub(super) fn leapfrog(state: &mut State, accelerations: &mut Vec<[f64; 3]>, settings: &SimulationSettings) {
+    let half_step = settings.step * 0.5;
+    for ((position, velocity), acceleration) in state.positions.iter_mut().zip(&mut state.velocities).zip(accelerations.iter()) {
+        for axis in 0..3 {
+            velocity[axis] += acceleration[axis] * half_step;
+            position[axis] += velocity[axis] * settings.step;
+        }
+    }
+
+    *accelerations = settings.force.accelerations(&state.positions, &state.masses, settings.gravity, settings.softening);
+    for (velocity, acceleration) in state.velocities.iter_mut().zip(accelerations.iter()) {
+        for axis in 0..3 {
+            velocity[axis] += acceleration[axis] * half_step;
+        }
+    }
+}
+
+//Dormand-Prince tableau for the autonomous gravity problem, the last row doubles as the fifth order weights
+const A: [[f64; 6]; 7] = [
+    [0.0; 6],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0, 0.0, 0.0],
+    [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0, 0.0],
+    [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0]
+];
+//Difference between the fifth and fourth order weights
+const E: [f64; 7] = [71.0 / 57600.0, 0.0, -71.0 / 16695.0, 71.0 / 1920.0, -17253.0 / 339200.0, 22.0 / 525.0, -1.0 / 40.0];
+
+const MIN_FACTOR: f64 = 0.2;
+const MAX_FACTOR: f64 = 5.0;
+const SAFETY: f64 = 0.9;
+const MAX_REJECTIONS: usize = 32;
+
+//Takes one accepted step starting with the proposed step size, which is updated for the next call.
+//Returns the size of the step that was taken.
+pub(super) fn dormand_prince(state: &mut State, step: &mut f64, settings: &SimulationSettings) -> f64 {
+    let start = pack(state);
+    let mut stages: [Vec<f64>; 7] = Default::default();
+    stages[0] = derivative(&start, &state.masses, settings);
+
+    let mut rejections = 0;
+    loop {
+        let h = *step;
+        let mut end = start.clone();
+        for stage in 1..7 {
+            end.copy_from_slice(&start);
+            for (k, a) in stages[..stage].iter().zip(A[stage]) {
+                for (y, k) in end.iter_mut().zip(k) {
+                    *y += h * a * k;
+                }
+            }
+            stages[stage] = derivative(&end, &state.masses, settings);
+        }
+
+        let error = error_norm(&start, &end, &stages, h, settings.tolerance);
+        let factor = if error == 0.0 { MAX_FACTOR } else { (SAFETY * error.powf(-0.2)).clamp(MIN_FACTOR, MAX_FACTOR) };
+        *step = h * if factor.is_nan() { MIN_FACTOR } else { factor };
+
+        //After too many rejections the last attempt is accepted so the simulation keeps going
+        rejections += 1;
+        if error <= 1.0 || rejections > MAX_REJECTIONS {
+            unpack(&end, state);
+            return h;
+        }
+    }
+}
+
+fn error_norm(start: &[f64], end: &[f64], stages: &[Vec<f64>; 7], h: f64, tolerance: f64) -> f64 {
+    if start.is_empty() {
+        return 0.0;
+    }
+
+    let mut sum = 0.0;
+    for index in 0..start.len() {
+        let estimate = h * stages.iter().zip(E).map(|(k, e)| e * k[index]).sum::<f64>();
+        let scale = tolerance * (1.0 + start[index].abs().max(end[index].abs()));
+        sum += (estimate / scale).powi(2);
+    }
+    (sum / start.len() as f64).sqrt()
+}
+
+//Positions and velocities of every body as one flat vector x y z vx vy vz ...
+fn pack(state: &State) -> Vec<f64> {
+    state.positions.iter().zip(&state.velocities)
+        .flat_map(|(position, velocity)| position.iter().chain(velocity).copied())
+        .collect()
+}
+
+fn unpack(y: &[f64], state: &mut State) {
+    for ((values, position), velocity) in y.chunks(6).zip(&mut state.positions).zip(&mut state.velocities) {
+        position.copy_from_slice(&values[..3]);
+        velocity.copy_from_slice(&values[3..]);
+    }
+}
+
+fn derivative(y: &[f64], masses: &[f64], settings: &SimulationSettings) -> Vec<f64> {
+    let positions = y.chunks(6).map(|values| [values[0], values[1], values[2]]).collect::<Vec<_>>();
+    let accelerations = settings.force.accelerations(&positions, masses, settings.gravity, settings.softening);
+    y.chunks(6).zip(accelerations)
+        .flat_map(|(values, acceleration)| [values[3], values[4], values[5]].into_iter().chain(acceleration))
+        .collect()
+}
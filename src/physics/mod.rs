@@ -0,0 +1,197 @@
+use log::warn;
+use crate::trajectory::{Frame, Trajectory};
+
+mod barnes_hut;
+#[cfg(not(target_arch = "wasm32"))]
+mod benchmark;
+mod diagnostics;
+mod events;
+mod gravity;
+mod integrator;
+mod orbit;
+mod presets;
+mod random;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use benchmark::compare;
+pub use diagnostics::{analyze, diagnose, drift, Diagnostics, Drift};
+pub use events::Detector;
+pub use gravity::ForceEvaluator;
+pub use integrator::Integrator;
+pub use orbit::{osculating, primary, Elements, Osculating};
+pub use presets::Preset;
+
+//Newtonian constant of gravitation in SI units
+pub const GRAVITATIONAL_CONSTANT: f64 = 6.674e-11;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimulationSettings {
+    pub step: f64,
+    pub gravity: f64,
+    pub softening: f64,
+    pub force: ForceEvaluator,
+    pub integrator: Integrator,
+    //Accepted local error relative to the magnitude of the state, only used by adaptive integrators
+    pub tolerance: f64,
+    //Run on a compute shader if the device supports it, otherwise this falls back to the CPU
+    pub gpu: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    pub positions: Vec<[f64; 3]>,
+    pub velocities: Vec<[f64; 3]>,
+    pub masses: Vec<f64>,
+}
+
+//Steps kept in the history before neighbouring ones are combined
+const MAX_STEP_SAMPLES: usize = 4096;
+
+//Sizes of the steps taken so far. A long run doesn't grow it: once it is full every two neighbouring samples
+//become the smaller of them and each sample covers twice as many steps, so stretches where the solver slowed down stay visible.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepHistory {
+    pub count: usize,
+    pub last: f64,
+    pub largest: f64,
+    pub smallest: f64,
+    //Time at the end of the smallest step
+    pub smallest_time: f64,
+    //Time at the end and size of the smallest step of every stride steps
+    samples: Vec<(f64, f64)>,
+    stride: usize,
+}
+
+//Integrates the bodies forward in time, producing frames as it goes
+pub struct Simulation {
+    settings: SimulationSettings,
+    state: State,
+    time: f64,
+    accelerations: Vec<[f64; 3]>,
+    //Proposed size of the next adaptive step
+    step: f64,
+    history: StepHistory,
+    //Body ids of the starting frame, the simulated bodies stay the same
+    ids: Option<Vec<usize>>,
+}
+
+impl SimulationSettings {
+    pub fn new(step: f64) -> SimulationSettings {
+        SimulationSettings {
+            step,
+            gravity: GRAVITATIONAL_CONSTANT,
+            softening: 0.0,
+            force: ForceEvaluator::default(),
+            integrator: Integrator::default(),
+            tolerance: 1e-9,
+            gpu: false
+        }
+    }
+}
+
+impl Simulation {
+    //Starts from the last frame of the trajectory, bodies without a mass are treated as test particles
+    pub fn new(settings: SimulationSettings, trajectory: &Trajectory) -> Simulation {
+        let index = trajectory.len().checked_sub(1);
+        let last = index.and_then(|index| trajectory.frame(index));
+        let positions = last.as_ref().map(|frame| frame.positions.clone()).unwrap_or_default();
+        //Velocities the last frame doesn't carry are differenced from the frames before it, which only works with their times
+        let differentiable = last.as_ref().is_some_and(|frame| frame.velocities.is_some() || frame.time.is_some() && trajectory.len() > 1);
+        let velocities = index.filter(|_| differentiable)
+            .and_then(|index| trajectory.velocities(index))
+            .unwrap_or_else(|| {
+                if !positions.is_empty() {
+                    warn!("The last frame has neither velocities nor a time, the simulation starts with the bodies at rest");
+                }
+                vec![[0.0; 3]; positions.len()]
+            });
+        let ids = last.as_ref().and_then(|frame| frame.ids.clone());
+        let masses = (0..positions.len())
+            .map(|entry| last.as_ref().map_or(entry, |frame| frame.body(entry)))
+            .map(|body| trajectory.bodies.get(body).and_then(|body| body.mass).unwrap_or_default())
+            .collect();
+
+        let time = trajectory.end_time();
+        let state = State { positions, velocities, masses };
+        let accelerations = settings.force.accelerations(&state.positions, &state.masses, settings.gravity, settings.softening);
+        Simulation { step: settings.step, settings, state, time, accelerations, history: StepHistory::new(), ids }
+    }
+
+    pub fn settings(&self) -> &SimulationSettings {
+        &self.settings
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn step(&self) -> f64 {
+        self.settings.step
+    }
+
+    pub fn history(&self) -> &StepHistory {
+        &self.history
+    }
+
+    pub fn advance(&mut self) {
+        let step = match self.settings.integrator {
+            Integrator::Leapfrog => {
+                integrator::leapfrog(&mut self.state, &mut self.accelerations, &self.settings);
+                self.settings.step
+            }
+            Integrator::DormandPrince => integrator::dormand_prince(&mut self.state, &mut self.step, &self.settings)
+        };
+        self.time += step;
+        self.history.record(self.time, step);
+    }
+
+    pub fn frame(&self) -> Frame {
+        Frame {
+            time: Some(self.time),
+            positions: self.state.positions.clone(),
+            velocities: Some(self.state.velocities.clone()),
+            ids: self.ids.clone()
+        }
+    }
+}
+
+impl StepHistory {
+    fn new() -> StepHistory {
+        StepHistory { count: 0, last: 0.0, largest: 0.0, smallest: 0.0, smallest_time: 0.0, samples: Vec::new(), stride: 1 }
+    }
+
+    //Accepted steps as (time at the end, size), the smallest step of each stretch of stride steps
+    pub fn samples(&self) -> &[(f64, f64)] {
+        &self.samples
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    fn record(&mut self, time: f64, step: f64) {
+        if self.count == 0 || step < self.smallest {
+            self.smallest = step;
+            self.smallest_time = time;
+        }
+        self.largest = self.largest.max(step);
+        self.last = step;
+
+        let smaller = |a: (f64, f64), b: (f64, f64)| if b.1 < a.1 { b } else { a };
+        match self.samples.last_mut() {
+            Some(sample) if !self.count.is_multiple_of(self.stride) => *sample = smaller(*sample, (time, step)),
+            _ => {
+                if self.samples.len() == MAX_STEP_SAMPLES {
+                    self.samples = self.samples.chunks(2).map(|pair| pair.iter().copied().reduce(smaller).unwrap()).collect();
+                    self.stride *= 2;
+                }
+                self.samples.push((time, step));
+            }
+        }
+        self.count += 1;
+    }
+}
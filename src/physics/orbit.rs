@@ -0,0 +1,163 @@
+use std::f64::consts::TAU;
+use crate::trajectory::Trajectory;
+use super::diagnostics::{cross, length, sub};
+use super::GRAVITATIONAL_CONSTANT;
+
+//Classical orbital elements, lengths in meters and angles in radians.
+//Unbound orbits have a negative semi-major axis and the hyperbolic mean anomaly.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Elements {
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub ascending_node: f64,
+    pub argument_of_periapsis: f64,
+    pub mean_anomaly: f64,
+}
+
+//Elements of the two-body orbit that touches the current motion of a body around its primary
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Osculating {
+    pub time: f64,
+    pub elements: Elements,
+    pub true_anomaly: f64,
+    //None for unbound orbits
+    pub period: Option<f64>,
+}
+
+impl Elements {
+    //Position and velocity relative to the central body, mu is the gravitational constant times both masses
+    pub fn to_state(self, mu: f64) -> ([f64; 3], [f64; 3]) {
+        let a = self.semi_major_axis;
+        let e = self.eccentricity;
+        let anomaly = eccentric_anomaly(self.mean_anomaly, e);
+        let (sin, cos) = anomaly.sin_cos();
+        let root = (1.0 - e * e).sqrt();
+        let mean_motion = (mu / (a * a * a)).sqrt();
+        let rate = mean_motion / (1.0 - e * cos);
+
+        let position = [a * (cos - e), a * root * sin, 0.0];
+        let velocity = [-a * rate * sin, a * rate * root * cos, 0.0];
+        (self.rotate(position), self.rotate(velocity))
+    }
+
+    //Inverse of to_state that also returns the true anomaly, None if the body moves straight towards or away from the central body
+    pub fn from_state(position: [f64; 3], velocity: [f64; 3], mu: f64) -> Option<(Elements, f64)> {
+        let distance = length(position);
+        let momentum = cross(position, velocity);
+        let momentum_length = length(momentum);
+        if mu <= 0.0 || distance == 0.0 || momentum_length == 0.0 {
+            return None;
+        }
+
+        let speed = dot(velocity, velocity);
+        let radial = dot(position, velocity);
+        let eccentricity_vector = [0, 1, 2].map(|axis| ((speed - mu / distance) * position[axis] - radial * velocity[axis]) / mu);
+        let eccentricity = length(eccentricity_vector);
+        let semi_major_axis = -mu / (speed - 2.0 * mu / distance);
+        let inclination = (momentum[2] / momentum_length).clamp(-1.0, 1.0).acos();
+
+        //The node is undefined for orbits in the reference plane, angles are measured from the x axis then
+        let [x, y, _] = momentum;
+        let ascending_node = if x.hypot(y) > 1e-12 * momentum_length { x.atan2(-y) } else { 0.0 };
+        let node = [ascending_node.cos(), ascending_node.sin(), 0.0];
+        let normal = momentum.map(|value| value / momentum_length);
+        let across = cross(normal, node);
+        let angle = |vector: [f64; 3]| dot(vector, across).atan2(dot(vector, node));
+
+        //Circular orbits have no periapsis, it is put on the node
+        let argument_of_periapsis = if eccentricity > 1e-12 { angle(eccentricity_vector) } else { 0.0 };
+        let true_anomaly = (angle(position) - argument_of_periapsis).rem_euclid(TAU);
+        let mean_anomaly = if eccentricity < 1.0 {
+            let anomaly = ((1.0 - eccentricity * eccentricity).sqrt() * true_anomaly.sin()).atan2(eccentricity + true_anomaly.cos());
+            anomaly - eccentricity * anomaly.sin()
+        } else {
+            let anomaly = 2.0 * (((eccentricity - 1.0) / (eccentricity + 1.0)).sqrt() * (true_anomaly / 2.0).tan()).atanh();
+            eccentricity * anomaly.sinh() - anomaly
+        };
+
+        let elements = Elements {
+            semi_major_axis,
+            eccentricity,
+            inclination,
+            ascending_node: ascending_node.rem_euclid(TAU),
+            argument_of_periapsis: argument_of_periapsis.rem_euclid(TAU),
+            mean_anomaly: if eccentricity < 1.0 { mean_anomaly.rem_euclid(TAU) } else { mean_anomaly }
+        };
+        Some((elements, true_anomaly))
+    }
+
+    //Time of one revolution
+    pub fn period(&self, mu: f64) -> f64 {
+        TAU * (self.semi_major_axis.powi(3) / mu).sqrt()
+    }
+
+    //The same orbit after the given time, only the mean anomaly moves
+    pub fn propagate(&self, mu: f64, time: f64) -> Elements {
+        let mean_motion = TAU / self.period(mu);
+        Elements { mean_anomaly: self.mean_anomaly + mean_motion * time, ..*self }
+    }
+
+    //From the orbital plane with the periapsis on the x axis into the reference frame
+    fn rotate(&self, [x, y, _]: [f64; 3]) -> [f64; 3] {
+        let (sin_node, cos_node) = self.ascending_node.sin_cos();
+        let (sin_inclination, cos_inclination) = self.inclination.sin_cos();
+        let (sin_periapsis, cos_periapsis) = self.argument_of_periapsis.sin_cos();
+
+        let x1 = cos_periapsis * x - sin_periapsis * y;
+        let y1 = sin_periapsis * x + cos_periapsis * y;
+        let y2 = cos_inclination * y1;
+        let z2 = sin_inclination * y1;
+        [cos_node * x1 - sin_node * y2, sin_node * x1 + cos_node * y2, z2]
+    }
+}
+
+//Osculating elements of a body around a primary at a frame, with the masses of both and the constant of the simulation.
+//Velocities are finite-differenced if the frame doesn't carry them.
+pub fn osculating(trajectory: &Trajectory, index: usize, body: usize, primary: usize) -> Option<Osculating> {
+    let frame = trajectory.frame(index)?;
+    let velocities = trajectory.velocities(index)?;
+    let entry = |body: usize| (0..frame.positions.len()).find(|&entry| frame.body(entry) == body);
+    let (body_entry, primary_entry) = (entry(body)?, entry(primary)?);
+
+    let gravity = trajectory.simulation.map_or(GRAVITATIONAL_CONSTANT, |settings| settings.gravity);
+    let mass = |body: usize| trajectory.bodies.get(body).and_then(|body| body.mass).unwrap_or_default();
+    let mu = gravity * (mass(body) + mass(primary));
+
+    let position = sub(frame.positions[body_entry], frame.positions[primary_entry]);
+    let velocity = sub(velocities[body_entry], velocities[primary_entry]);
+    let (elements, true_anomaly) = Elements::from_state(position, velocity, mu)?;
+    Some(Osculating {
+        time: trajectory.time(index)?,
+        elements,
+        true_anomaly,
+        period: (elements.eccentricity < 1.0).then(|| elements.period(mu))
+    })
+}
+
+//The most massive other body, the one a body is most likely orbiting
+pub fn primary(trajectory: &Trajectory, body: usize) -> Option<usize> {
+    (0..trajectory.bodies.len())
+        .filter(|&other| other != body)
+        .filter_map(|other| Some((other, trajectory.bodies[other].mass?)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(other, _)| other)
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+//Solves Kepler's equation M = E - e sin E with Newton's method
+fn eccentric_anomaly(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let mean_anomaly = mean_anomaly.rem_euclid(TAU);
+    let mut anomaly = if eccentricity > 0.8 { std::f64::consts::PI } else { mean_anomaly };
+    for _ in 0..50 {
+        let delta = (anomaly - eccentricity * anomaly.sin() - mean_anomaly) / (1.0 - eccentricity * anomaly.cos());
+        anomaly -= delta;
+        if delta.abs() < 1e-14 {
+            break;
+        }
+    }
+    anomaly
+}
@@ -0,0 +1,271 @@
+use std::f64::consts::{PI, TAU};
+use std::fmt::{Display, Formatter};
+use crate::trajectory::{Body, Frame, Trajectory};
+use super::orbit::Elements;
+use super::random::Random;
+use super::{ForceEvaluator, SimulationSettings, GRAVITATIONAL_CONSTANT};
+
+const ASTRONOMICAL_UNIT: f64 = 1.495978707e11;
+const DAY: f64 = 86400.0;
+const SOLAR_MASS: f64 = 1.98847e30;
+const JUPITER_MASS: f64 = 1.89819e27;
+//Above this many bodies the presets use the Barnes-Hut tree instead of direct summation
+const TREE_THRESHOLD: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    SolarSystem,
+    FigureEight,
+    Disk,
+    Plummer,
+    CircumbinaryPlanet
+}
+
+//Name, mass in kg, radius in m, color and J2000 elements a [AU], e, I, L, long. perihelion, long. asc. node [deg]
+const PLANETS: [(&str, f64, f64, u32, [f64; 6]); 8] = [
+    ("Mercury", 3.3011e23, 2.4397e6, 0x9e9e9e, [0.38709927, 0.20563593, 7.00497902, 252.25032350, 77.45779628, 48.33076593]),
+    ("Venus", 4.8675e24, 6.0518e6, 0xe6c27a, [0.72333566, 0.00677672, 3.39467605, 181.97909950, 131.60246718, 76.67984255]),
+    ("Earth", 6.0456e24, 6.371e6, 0x2f6fd6, [1.00000261, 0.01671123, -0.00001531, 100.46457166, 102.93768193, 0.0]),
+    ("Mars", 6.4171e23, 3.3895e6, 0xc1440e, [1.52371034, 0.09339410, 1.84969142, -4.55343205, -23.94362959, 49.55953891]),
+    ("Jupiter", 1.89819e27, 6.9911e7, 0xd8a66c, [5.20288700, 0.04838624, 1.30439695, 34.39644051, 14.72847983, 100.47390909]),
+    ("Saturn", 5.6834e26, 5.8232e7, 0xe3d19a, [9.53667594, 0.05386179, 2.48599187, 49.95424423, 92.59887831, 113.66242448]),
+    ("Uranus", 8.6810e25, 2.5362e7, 0x9fe3e8, [19.18916464, 0.04725744, 0.77263783, 313.23810451, 170.95427630, 74.01692503]),
+    ("Neptune", 1.02413e26, 2.4622e7, 0x4b70dd, [30.06992276, 0.00859048, 1.77004347, -55.12002969, 44.96476227, 131.78422574])
+];
+
+impl Preset {
+    pub fn parse(name: &str) -> Option<Preset> {
+        match name {
+            "solar-system" => Some(Preset::SolarSystem),
+            "figure-eight" => Some(Preset::FigureEight),
+            "disk" => Some(Preset::Disk),
+            "plummer" => Some(Preset::Plummer),
+            "circumbinary" => Some(Preset::CircumbinaryPlanet),
+            _ => None
+        }
+    }
+
+    //Initial conditions with a live simulation attached, the same seed always gives the same bodies.
+    //The count only applies to the disk and the Plummer sphere.
+    pub fn generate(self, seed: u64, count: Option<usize>) -> Trajectory {
+        let mut random = Random::new(seed);
+        let mut scene = match self {
+            Preset::SolarSystem => solar_system(),
+            Preset::FigureEight => figure_eight(),
+            Preset::Disk => disk(&mut random, count.unwrap_or(500)),
+            Preset::Plummer => plummer(&mut random, count.unwrap_or(1000)),
+            Preset::CircumbinaryPlanet => circumbinary(&mut random)
+        };
+        scene.center_on_barycentre();
+
+        if scene.bodies.len() > TREE_THRESHOLD {
+            scene.settings.force = ForceEvaluator::BarnesHut { opening_angle: 0.5 };
+        }
+
+        let frame = Frame { time: Some(0.0), positions: scene.positions, velocities: Some(scene.velocities), ids: None };
+        let mut trajectory = Trajectory::new(scene.bodies, vec![frame]);
+        trajectory.simulation = Some(scene.settings);
+        trajectory
+    }
+}
+
+struct Scene {
+    bodies: Vec<Body>,
+    positions: Vec<[f64; 3]>,
+    velocities: Vec<[f64; 3]>,
+    settings: SimulationSettings
+}
+
+impl Scene {
+    fn new(settings: SimulationSettings) -> Scene {
+        Scene { bodies: Vec::new(), positions: Vec::new(), velocities: Vec::new(), settings }
+    }
+
+    fn push(&mut self, body: Body, position: [f64; 3], velocity: [f64; 3]) {
+        self.bodies.push(body);
+        self.positions.push(position);
+        self.velocities.push(velocity);
+    }
+
+    //Moves into the frame where the center of mass is at rest in the origin
+    fn center_on_barycentre(&mut self) {
+        let masses = self.bodies.iter().map(|body| body.mass.unwrap_or_default()).collect::<Vec<_>>();
+        let total = masses.iter().sum::<f64>();
+        if total <= 0.0 {
+            return;
+        }
+
+        for vectors in [&mut self.positions, &mut self.velocities] {
+            let mut center = [0.0; 3];
+            for (vector, mass) in vectors.iter().zip(&masses) {
+                for axis in 0..3 {
+                    center[axis] += vector[axis] * mass / total;
+                }
+            }
+            for vector in vectors.iter_mut() {
+                for axis in 0..3 {
+                    vector[axis] -= center[axis];
+                }
+            }
+        }
+    }
+}
+
+//Colors are given as 0xrrggbb
+fn body(name: &str, mass: f64, radius: Option<f64>, color: Option<u32>) -> Body {
+    Body {
+        name: Some(name.to_string()),
+        mass: Some(mass),
+        radius,
+        color: color.map(|color| {
+            let [_, r, g, b] = color.to_be_bytes().map(|channel| channel as f32 / 255.0);
+            [r, g, b, 1.0]
+        }),
+        light: None,
+        texture: None
+    }
+}
+
+fn star(name: &str, mass: f64, radius: f64, color: u32) -> Body {
+    Body { light: Some(1.0), ..body(name, mass, Some(radius), Some(color)) }
+}
+
+//The Sun and the eight planets at J2000 in the ecliptic frame, from the mean elements of Standish (JPL)
+fn solar_system() -> Scene {
+    let mut scene = Scene::new(SimulationSettings::new(DAY));
+    scene.push(star("Sun", SOLAR_MASS, 6.957e8, 0xfff4d6), [0.0; 3], [0.0; 3]);
+    for (name, mass, radius, color, [a, e, inclination, longitude, perihelion, node]) in PLANETS {
+        let elements = Elements {
+            semi_major_axis: a * ASTRONOMICAL_UNIT,
+            eccentricity: e,
+            inclination: inclination.to_radians(),
+            ascending_node: node.to_radians(),
+            argument_of_periapsis: (perihelion - node).to_radians(),
+            mean_anomaly: (longitude - perihelion).to_radians()
+        };
+        let (position, velocity) = elements.to_state(GRAVITATIONAL_CONSTANT * (SOLAR_MASS + mass));
+        scene.push(body(name, mass, Some(radius), Some(color)), position, velocity);
+    }
+    scene
+}
+
+//Periodic three body choreography of Chenciner and Montgomery in units where G = 1
+fn figure_eight() -> Scene {
+    let mut settings = SimulationSettings::new(1e-3);
+    settings.gravity = 1.0;
+
+    let mut scene = Scene::new(settings);
+    let position = [0.97000436, -0.24308753, 0.0];
+    let velocity = [-0.93240737, -0.86473146, 0.0];
+    scene.push(body("A", 1.0, None, Some(0xff5555)), position, velocity.map(|value| -value / 2.0));
+    scene.push(body("B", 1.0, None, Some(0x55ff55)), position.map(|value| -value), velocity.map(|value| -value / 2.0));
+    scene.push(body("C", 1.0, None, Some(0x5555ff)), [0.0; 3], velocity);
+    scene
+}
+
+//A solar mass star surrounded by planetesimals between 0.5 and 5 AU on nearly circular, nearly flat orbits,
+//uniform in radius which gives a surface density falling off with 1/r
+fn disk(random: &mut Random, count: usize) -> Scene {
+    let mut settings = SimulationSettings::new(DAY);
+    settings.softening = 1e-3 * ASTRONOMICAL_UNIT;
+
+    let mut scene = Scene::new(settings);
+    scene.push(star("Star", SOLAR_MASS, 6.957e8, 0xfff4d6), [0.0; 3], [0.0; 3]);
+    let mass = 1e-3 * SOLAR_MASS / count.max(1) as f64;
+    for index in 0..count {
+        let elements = Elements {
+            semi_major_axis: (0.5 + 4.5 * random.uniform()) * ASTRONOMICAL_UNIT,
+            eccentricity: 0.02 * random.uniform(),
+            inclination: (0.5 * random.uniform()).to_radians(),
+            ascending_node: TAU * random.uniform(),
+            argument_of_periapsis: TAU * random.uniform(),
+            mean_anomaly: TAU * random.uniform()
+        };
+        let (position, velocity) = elements.to_state(GRAVITATIONAL_CONSTANT * (SOLAR_MASS + mass));
+        scene.push(body(&format!("p{index}"), mass, None, None), position, velocity);
+    }
+    scene
+}
+
+//Star cluster in equilibrium following Aarseth, Hénon and Wielen (1974), in N-body units G = M = 1, E = -1/4
+fn plummer(random: &mut Random, count: usize) -> Scene {
+    let mut settings = SimulationSettings::new(1e-3);
+    settings.gravity = 1.0;
+    settings.softening = 0.01;
+
+    let mut scene = Scene::new(settings);
+    let scale = 3.0 * PI / 16.0;
+    let mass = 1.0 / count.max(1) as f64;
+    for index in 0..count {
+        //Cut off the sparse outer halo where the radius would diverge
+        let radius = loop {
+            let fraction = random.uniform();
+            let radius = 1.0 / (fraction.powf(-2.0 / 3.0) - 1.0).sqrt();
+            if radius.is_finite() && radius < 10.0 {
+                break radius;
+            }
+        };
+
+        //Von Neumann rejection sampling of the speed relative to the escape speed
+        let ratio = loop {
+            let (q, g) = (random.uniform(), 0.1 * random.uniform());
+            if g < q * q * (1.0 - q * q).powf(3.5) {
+                break q;
+            }
+        };
+        let speed = ratio * 2f64.sqrt() * (1.0 + radius * radius).powf(-0.25);
+
+        let position = direction(random).map(|value| value * radius * scale);
+        let velocity = direction(random).map(|value| value * speed / scale.sqrt());
+        scene.push(body(&format!("s{index}"), mass, None, None), position, velocity);
+    }
+    scene
+}
+
+//Kepler-16 like system: two stars on an eccentric 41 day orbit with a Saturn mass planet around both
+fn circumbinary(random: &mut Random) -> Scene {
+    let mut scene = Scene::new(SimulationSettings::new(0.05 * DAY));
+    let (primary, secondary, planet) = (0.69 * SOLAR_MASS, 0.20 * SOLAR_MASS, 0.333 * JUPITER_MASS);
+    scene.push(star("A", primary, 4.5e8, 0xffd27a), [0.0; 3], [0.0; 3]);
+
+    let binary = Elements { semi_major_axis: 0.224 * ASTRONOMICAL_UNIT, eccentricity: 0.16, ..Elements::default() };
+    let (position, velocity) = binary.to_state(GRAVITATIONAL_CONSTANT * (primary + secondary));
+    scene.push(star("B", secondary, 1.6e8, 0xff7a5a), position, velocity);
+
+    //The planet orbits the barycentre of the binary
+    let barycentre = position.map(|value| value * secondary / (primary + secondary));
+    let drift = velocity.map(|value| value * secondary / (primary + secondary));
+    let orbit = Elements {
+        semi_major_axis: 0.705 * ASTRONOMICAL_UNIT,
+        eccentricity: 0.007,
+        mean_anomaly: TAU * random.uniform(),
+        ..Elements::default()
+    };
+    let (position, velocity) = orbit.to_state(GRAVITATIONAL_CONSTANT * (primary + secondary + planet));
+    scene.push(
+        body("b", planet, Some(5.4e7), Some(0xc9b27c)),
+        [0, 1, 2].map(|axis| position[axis] + barycentre[axis]),
+        [0, 1, 2].map(|axis| velocity[axis] + drift[axis])
+    );
+    scene
+}
+
+//Uniformly distributed unit vector
+fn direction(random: &mut Random) -> [f64; 3] {
+    let z = 2.0 * random.uniform() - 1.0;
+    let angle = TAU * random.uniform();
+    let radius = (1.0 - z * z).sqrt();
+    [radius * angle.cos(), radius * angle.sin(), z]
+}
+
+impl Display for Preset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Preset::SolarSystem => write!(f, "solar-system"),
+            Preset::FigureEight => write!(f, "figure-eight"),
+            Preset::Disk => write!(f, "disk"),
+            Preset::Plummer => write!(f, "plummer"),
+            Preset::CircumbinaryPlanet => write!(f, "circumbinary")
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//Small seeded xorshift generator, the same seed always produces the same sequence
+pub(super) struct Random {
+    state: u64
+}
+
+impl Random {
+    pub(super) fn new(seed: u64) -> Random {
+        //The state must never be zero
+        Random { state: seed ^ 0x9e37_79b9_7f4a_7c15 | 1 }
+    }
+
+    pub(super) fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    //Uniform in [0, 1)
+    pub(super) fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    //Uniform inside the unit ball
+    pub(super) fn in_unit_ball(&mut self) -> [f64; 3] {
+        loop {
+            let point = [0; 3].map(|_| self.uniform() * 2.0 - 1.0);
+            if point.iter().map(|value| value * value).sum::<f64>() <= 1.0 {
+                return point;
+            }
+        }
+    }
+}
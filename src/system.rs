@@ -1,162 +1,441 @@
-use bytemuck::{Pod, Zeroable};
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::{vertex_attr_array, Buffer, BufferUsages, Device, Queue, VertexBufferLayout, VertexStepMode};
+use log::info;
+use crate::trajectory::{Event, Frame, Interpolation, Trajectory};
 #[cfg(target_arch = "wasm32")]
 use web_time::Duration;
-#[cfg(target_arch = "wasm32")]
-use web_time::Instant;
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Duration;
-#[cfg(not(target_arch = "wasm32"))]
-use std::time::Instant;
+use crate::clock::{Clock, RealTimeClock};
+use crate::physics::{self, Detector, Diagnostics, Drift, Osculating, Simulation};
 
-#[repr(C)]
-#[derive(Clone, Copy, Pod, Zeroable)]
-pub(super) struct PlanetInstance {
-    position: [f32; 4],
-    color: [f32; 4],
+pub struct System {
+    trajectory: Trajectory,
+    time: f64,
+    rate: f64,
+    paused: bool,
+    reversed: bool,
+    end_mode: EndMode,
+    interpolation: Interpolation,
+    clock: Box<dyn Clock>,
+    simulation: Option<Simulation>,
+    //Simulated time of a simulation that was handed over to the GPU
+    offloaded: Option<f64>,
+    //Simulation steps per stored frame, doubled whenever the stored frames are thinned out, and the steps since the last one
+    stride: usize,
+    skipped: usize,
+    //Body whose osculating elements are shown and the body they are relative to, the most massive other one if None
+    selected: Option<usize>,
+    primary: Option<usize>,
+    //Scans the frames for the event log, also the ones a live simulation adds
+    detector: Option<Detector>,
+    //Drift of the conserved quantities, measured when first asked for and then only over the frames added since
+    drift: Option<DriftLog>,
+    //Counts the times playback skipped over frames instead of moving through them
+    jumps: usize,
 }
 
-pub(super) struct System {
-    offset: usize,
-    num_planets: usize,
-    planets: Vec<Vec<PlanetInstance>>,
-    colors: Vec<[f32; 4]>,
-    interval: Duration,
-    last_update: Instant,
-    pub(super) planet_buffer: Buffer,
-    scale: f64,
+struct DriftLog {
+    first: Diagnostics,
+    drift: Drift,
+    //Time of the last measured frame and how many were measured
+    time: f64,
+    frames: usize,
 }
 
-//Data Format
-// x, y, z, x1, y1, z1, ... for every planet followed by new lines and the new cooridnates for the next time step
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EndMode {
+    #[default]
+    Loop,
+    PingPong,
+    Hold
+}
 
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+//Upper bound for simulation steps per update so a high rate can't stall rendering
+const MAX_SIMULATION_STEPS: usize = 1000;
+//Positions a live simulation may store over all frames, about 100 MB with their velocities
+const MAX_LIVE_POSITIONS: usize = 1 << 21;
 
 impl System {
-    pub(super) fn speed_up(&mut self) {
-        self.interval = self.interval.div_f64(2.0);
+    pub fn speed_up(&mut self) {
+        self.rate *= 2.0;
     }
 
-    pub(super) fn slow_down(&mut self) {
-        self.interval = self.interval.mul_f64(2.0);
+    pub fn slow_down(&mut self) {
+        self.rate /= 2.0;
     }
 
-    pub(super)  fn new(device: &Device, content: String) -> System {
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
 
-        let mut planets = Vec::new();
-        let mut starting_planets = Vec::new();
-        for line in content.lines() {
-            let mut new_planets = Vec::new();
-            let mut iter = line.split_whitespace()
-                .map(|s| s.parse::<f64>().unwrap_or_default())
-                .peekable();
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
 
+    pub fn reverse(&mut self) {
+        if self.on_gpu() {
+            return;
+        }
+        self.reversed = !self.reversed;
+    }
 
-            while iter.peek().is_some() {
-                let x = iter.next().unwrap_or_default();
-                let y = iter.next().unwrap_or_default();
-                let z = iter.next().unwrap_or_default();
+    pub fn step_forward(&mut self) {
+        if self.on_gpu() {
+            return;
+        }
+        self.paused = true;
+        self.seek_frame(self.trajectory.frame_at(self.time) + 1);
+    }
 
-                new_planets.push(PlanetInstance {
-                    position: [x as f32, y as f32, z as f32, 1.0],
-                    color: [1.0, 1.0, 1.0, 1.0]
-                });
-            }
+    pub fn step_backward(&mut self) {
+        if self.on_gpu() {
+            return;
+        }
+        self.paused = true;
+        let current = self.trajectory.frame_at(self.time);
+        let on_frame = self.trajectory.time(current).is_some_and(|time| time >= self.time);
+        self.seek_frame(if on_frame { current.saturating_sub(1) } else { current });
+    }
 
-            if new_planets.len() > starting_planets.len() {
-                starting_planets = new_planets.clone();
-            }
+    pub fn jump_to_start(&mut self) {
+        self.seek_time(self.trajectory.start_time());
+    }
+
+    pub fn jump_to_end(&mut self) {
+        self.seek_time(self.trajectory.end_time());
+    }
+
+    pub fn seek_frame(&mut self, index: usize) {
+        let index = index.min(self.trajectory.len().saturating_sub(1));
+        if let Some(time) = self.trajectory.time(index) {
+            self.seek_time(time);
+        }
+    }
+
+    pub fn seek_time(&mut self, time: f64) {
+        if self.on_gpu() {
+            return;
+        }
+        let time = time.clamp(self.trajectory.start_time(), self.trajectory.end_time());
+        if self.trajectory.frame_at(time).abs_diff(self.trajectory.frame_at(self.time)) > 1 {
+            self.jumps += 1;
+        }
+        self.time = time;
+    }
+
+    //Seeks to a fraction between 0.0 and 1.0 of the whole recording
+    pub fn seek_fraction(&mut self, fraction: f64) {
+        let start = self.trajectory.start_time();
+        self.seek_time(start + (self.trajectory.end_time() - start) * fraction);
+    }
 
-            planets.push(new_planets);
+    pub fn next_end_mode(&mut self) {
+        if self.on_gpu() {
+            return;
         }
+        self.end_mode = match self.end_mode {
+            EndMode::Loop => EndMode::PingPong,
+            EndMode::PingPong => EndMode::Hold,
+            EndMode::Hold => EndMode::Loop
+        };
+        info!("End of data: {:?}", self.end_mode);
+    }
+
+    pub fn next_interpolation(&mut self) {
+        self.interpolation = self.interpolation.next();
+        info!("Interpolation: {}", self.interpolation);
+    }
+
+    //Logs where the simulation had to take its smallest steps
+    pub fn log_step_history(&self) {
+        let Some(history) = self.simulation.as_ref().map(Simulation::history).filter(|history| history.count > 0) else {
+            info!("No simulation steps taken");
+            return;
+        };
+
+        info!("{} steps, last {:e}, smallest {:e} at {:e}, largest {:e}",
+            history.count, history.last, history.smallest, history.smallest_time, history.largest);
+
+        let mut slowest = history.samples().to_vec();
+        slowest.sort_unstable_by(|a, b| a.1.total_cmp(&b.1));
+        let slowest = slowest.iter().take(5).map(|(time, step)| format!("{step:e} at {time:e}")).collect::<Vec<_>>();
+        info!("Smallest steps of {} stretches of {} steps: {}", history.samples().len(), history.stride(), slowest.join(", "));
+    }
 
-        if starting_planets.is_empty() {
-            starting_planets.push(PlanetInstance {
-                position: [0.0, 0.0, 0.0, 1.0],
-                color: [1.0, 1.0, 1.0, 1.0]
-            });
+    //Logs the conserved quantities at the current frame and how much they drifted over the frames so far
+    pub fn log_diagnostics(&mut self) {
+        if self.on_gpu() {
+            return;
         }
+        let Some(current) = physics::diagnose(&self.trajectory, self.trajectory.frame_at(self.time)) else { return };
+        let energy = match (current.energy(), current.kinetic) {
+            (Some(energy), Some(kinetic)) => format!("energy {energy:e} (kinetic {kinetic:e}, potential {:e})", current.potential),
+            _ => format!("potential energy {:e}, no kinetic energy without timestamps", current.potential)
+        };
+        info!("t={:e}: {energy}, momentum {:?}, angular momentum {:?}, barycentre {:?}",
+            current.time, current.momentum, current.angular_momentum, current.barycentre);
+
+        self.measure_drift();
+        let Some(log) = &self.drift else { return };
+        let energy = log.drift.energy.map_or(String::from("unknown"), |energy| format!("{energy:e}"));
+        info!("Drift over {} frames: energy {energy}, momentum {:e}, angular momentum {:e}, barycentre moved {:e}",
+            log.frames, log.drift.momentum, log.drift.angular_momentum, log.drift.barycentre);
+    }
 
-        let max_distance = starting_planets.iter()
-            .map(|planet| (planet.position.iter().map(|&x| x * x).sum::<f32>()).sqrt())
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .unwrap_or(1.0);
+    //Measures the frames after the last measured one, a live simulation may have dropped some in between
+    fn measure_drift(&mut self) {
+        let start = self.drift.as_ref().map_or(0, |log| self.trajectory.frame_at(log.time) + 1);
+        for index in start..self.trajectory.len() {
+            let Some(diagnostics) = physics::diagnose(&self.trajectory, index) else { continue };
+            let log = self.drift.get_or_insert(DriftLog { first: diagnostics, drift: Drift::default(), time: diagnostics.time, frames: 0 });
+            log.drift.add(&log.first, &diagnostics);
+            log.time = diagnostics.time;
+            log.frames += 1;
+        }
+    }
 
-        let scale = if max_distance.abs() > 0.01 {
-            10.0 / max_distance as f64
-        } else {
-            1.0
+    //Cycles through the bodies of the current frame and back to no selection
+    pub fn select_next_body(&mut self) {
+        if self.on_gpu() {
+            return;
+        }
+        let bodies = self.current_bodies();
+        self.selected = match self.selected {
+            None => bodies.first().copied(),
+            Some(body) => bodies.into_iter().find(|&other| other > body)
         };
+        self.primary = None;
+        match self.selected {
+            Some(body) => info!("Selected {}", self.trajectory.name(body)),
+            None => info!("Selection cleared")
+        }
+    }
 
-        let mut colors = Vec::new();
-        for i in 0..starting_planets.len() {
-            let mut random = Self::random(i as u32).to_ne_bytes().map(|b| b as f32 / 256.0);
-            random[3] = 0.0;
-            colors.push(random);
+    //Cycles the primary through the other bodies and back to the most massive one
+    pub fn select_next_primary(&mut self) {
+        let Some(body) = self.selected else { return };
+        self.primary = self.current_bodies().into_iter()
+            .find(|&other| other != body && self.primary.is_none_or(|primary| other > primary));
+        match self.primary.or_else(|| physics::primary(&self.trajectory, body)) {
+            Some(primary) => info!("Elements of {} relative to {}", self.trajectory.name(body), self.trajectory.name(primary)),
+            None => info!("{} has nothing to orbit", self.trajectory.name(body))
         }
+    }
 
-        let planet_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&starting_planets),
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST
-        });
+    //Bodies in the frame at the current time, also the ones without a # body line, in ascending order
+    fn current_bodies(&self) -> Vec<usize> {
+        let Some(frame) = self.trajectory.frame(self.trajectory.frame_at(self.time)) else { return Vec::new() };
+        let mut bodies = (0..frame.positions.len()).map(|entry| frame.body(entry)).collect::<Vec<_>>();
+        bodies.sort_unstable();
+        bodies
+    }
+
+    //Osculating elements of the selected body at the current frame
+    pub fn osculating(&self) -> Option<Osculating> {
+        let body = self.selected?;
+        let primary = self.primary.or_else(|| physics::primary(&self.trajectory, body))?;
+        physics::osculating(&self.trajectory, self.trajectory.frame_at(self.time), body, primary)
+    }
+
+    //One line summary of the selected orbit, angles in degrees
+    pub fn orbit_status(&self) -> Option<String> {
+        let body = self.selected?;
+        let Some(osculating) = self.osculating() else {
+            return Some(format!("{}: no orbit", self.trajectory.name(body)));
+        };
+
+        let elements = osculating.elements;
+        let period = osculating.period.map_or(String::from("unbound"), |period| format!("{period:.4e}"));
+        Some(format!("{}: a={:.4e} e={:.5} i={:.3}° Ω={:.3}° ω={:.3}° ν={:.3}° P={period}",
+            self.trajectory.name(body), elements.semi_major_axis, elements.eccentricity, elements.inclination.to_degrees(),
+            elements.ascending_node.to_degrees(), elements.argument_of_periapsis.to_degrees(), osculating.true_anomaly.to_degrees()))
+    }
+
+    //Fills the event log as playback reaches the frames, also the ones a live simulation adds
+    pub fn detect_events(&mut self, encounter_distance: Option<f64>) {
+        self.detector = Some(Detector::new(encounter_distance));
+    }
+
+    //Fills the event log from all frames right away
+    pub fn scan_events(&mut self) {
+        if let Some(detector) = &mut self.detector {
+            detector.scan(&mut self.trajectory);
+        }
+    }
+
+    //Pauses on the next or previous event in the log, which is filled from all frames first
+    pub fn jump_to_next_event(&mut self) {
+        self.scan_events();
+        self.jump_to_event(self.trajectory.next_event(self.time).copied());
+    }
+
+    pub fn jump_to_previous_event(&mut self) {
+        self.scan_events();
+        self.jump_to_event(self.trajectory.previous_event(self.time).copied());
+    }
+
+    fn jump_to_event(&mut self, event: Option<Event>) {
+        if self.on_gpu() {
+            return;
+        }
+        let Some(event) = event else {
+            info!("No further events");
+            return;
+        };
+
+        self.paused = true;
+        self.seek_time(event.time);
+        info!("{}", self.trajectory.describe(&event));
+    }
+
+    pub fn new(trajectory: Trajectory) -> System {
+        let simulation = trajectory.simulation.map(|settings| Simulation::new(settings, &trajectory));
+
+        //Play back ten frames per second on average
+        let step = simulation.as_ref().map_or(trajectory.average_step(), Simulation::step);
+        let rate = step / FRAME_INTERVAL.as_secs_f64();
 
         System {
-            offset: 0,
-            planets,
-            planet_buffer,
-            interval: Duration::from_millis(100),
-            last_update: Instant::now(),
-            num_planets: starting_planets.len(),
-            scale,
-            colors
+            time: trajectory.start_time(),
+            rate,
+            paused: false,
+            reversed: false,
+            end_mode: EndMode::default(),
+            interpolation: Interpolation::default(),
+            trajectory,
+            clock: Box::new(RealTimeClock::new()),
+            simulation,
+            offloaded: None,
+            stride: 1,
+            skipped: 0,
+            selected: None,
+            primary: None,
+            detector: None,
+            drift: None,
+            jumps: 0
         }
     }
 
-    fn random(seed: u32) -> u32 {
-        let state = seed;
-        state.wrapping_mul(1664525).wrapping_add(1013904223)
+    //Current playback time
+    pub fn time(&self) -> f64 {
+        self.time
     }
 
-    pub(super) fn step(&mut self, queue: &Queue) -> u32 {
-        let planets_len = self.planets.len();
-        let Some(mut planets) = self.planets.get(self.offset).cloned() else {
+    pub fn trajectory(&self) -> &Trajectory {
+        &self.trajectory
+    }
+
+    //Changes whenever playback jumps, seeking further than the next frame or starting over
+    pub fn jumps(&self) -> usize {
+        self.jumps
+    }
+
+    //Hands the live simulation over to the caller, which from now on takes the steps counted by offloaded_steps
+    pub fn offload_simulation(&mut self) -> Option<&Simulation> {
+        let simulation = self.simulation.as_ref()?;
+        self.offloaded = Some(simulation.time());
+        Some(simulation)
+    }
+
+    //The offloaded simulation only runs forward and its frames stay on the GPU,
+    //so nothing that needs the frames or moves the time works while it runs
+    fn on_gpu(&self) -> bool {
+        if self.offloaded.is_some() {
+            info!("Not available while the simulation runs on the GPU");
+        }
+        self.offloaded.is_some()
+    }
+
+    //Advances the clock and returns the number of steps the offloaded simulation needs to catch up
+    pub fn offloaded_steps(&mut self) -> usize {
+        let (Some(simulated), Some(simulation)) = (&mut self.offloaded, &self.simulation) else {
             return 0;
         };
 
-        for (planet, color) in planets.iter_mut().zip(self.colors.iter()) {
-            planet.position[0] *= self.scale as f32;
-            planet.position[1] *= self.scale as f32;
-            planet.position[2] *= self.scale as f32;
-            planet.color = *color;
+        let elapsed = self.clock.tick();
+        if !self.paused {
+            self.time += elapsed.as_secs_f64() * self.rate;
         }
 
-        let now = Instant::now();
-        let intervals = (now - self.last_update).as_millis() / self.interval.as_millis();
-        self.offset += intervals as usize;
-        self.last_update += self.interval * intervals as u32;
+        let step = simulation.step();
+        let steps = (((self.time - *simulated) / step).max(0.0) as usize).min(MAX_SIMULATION_STEPS);
+        *simulated += steps as f64 * step;
+        if steps == MAX_SIMULATION_STEPS {
+            self.time = *simulated;
+        }
+        steps
+    }
 
-        if self.offset >= planets_len {
-            self.offset = 0;
+    fn advance(&mut self) {
+        let elapsed = self.clock.tick();
+        if !self.paused {
+            let direction = if self.reversed { -1.0 } else { 1.0 };
+            self.time += elapsed.as_secs_f64() * self.rate * direction;
         }
 
+        //A live simulation extends the trajectory instead of wrapping around at its end
+        if let Some(simulation) = &mut self.simulation {
+            let bodies = simulation.state().positions.len().max(1);
+            for _ in 0..MAX_SIMULATION_STEPS {
+                if self.trajectory.end_time() >= self.time {
+                    break;
+                }
+                simulation.advance();
+                self.skipped += 1;
+                if self.skipped < self.stride {
+                    continue;
+                }
+                self.skipped = 0;
+                if !self.trajectory.push(simulation.frame()) {
+                    self.simulation = None;
+                    break;
+                }
+            }
 
-        queue.write_buffer(&self.planet_buffer, 0, bytemuck::cast_slice(planets.as_slice()));
-        self.num_planets as u32
-    }
-}
+            //Over the budget every other frame goes and from then on only every other step is stored
+            if self.trajectory.len() * bodies > MAX_LIVE_POSITIONS {
+                self.scan_events();
+                self.trajectory.decimate();
+                self.stride *= 2;
+                info!("Thinned out the stored frames to {}, storing one every {} steps", self.trajectory.len(), self.stride);
+                if let Some(detector) = &mut self.detector {
+                    detector.skip_to(self.trajectory.len());
+                }
+            }
 
-impl PlanetInstance {
-    pub(super) fn desc() -> VertexBufferLayout<'static> {
-        VertexBufferLayout {
-            array_stride: size_of::<Self>() as u64,
-            step_mode: VertexStepMode::Instance,
-            attributes: const {
-                &vertex_attr_array![
-                    2 => Float32x4,
-                    3 => Float32x4
-                ]
+            if self.simulation.is_some() {
+                self.time = self.time.clamp(self.trajectory.start_time(), self.trajectory.end_time());
+                return;
             }
         }
+
+        let start = self.trajectory.start_time();
+        let end = self.trajectory.end_time();
+        match self.end_mode {
+            //The last frame is held for one average step before playback starts over
+            EndMode::Loop => if self.time >= end + self.trajectory.average_step() {
+                self.time = start;
+                self.jumps += 1;
+            } else if self.time < start {
+                self.time = end;
+                self.jumps += 1;
+            },
+            EndMode::PingPong => if self.time > end || self.time < start {
+                let edge = if self.time > end { end } else { start };
+                self.time = (2.0 * edge - self.time).clamp(start, end);
+                self.reversed = !self.reversed;
+            },
+            EndMode::Hold => self.time = self.time.clamp(start, end)
+        }
     }
-}
\ No newline at end of file
+
+    //Advances playback by the time that passed on the clock and returns the bodies at the new time
+    pub fn step(&mut self) -> Frame {
+        self.advance();
+        let current = self.trajectory.frame_at(self.time);
+        if let Some(detector) = &mut self.detector {
+            detector.scan_to(&mut self.trajectory, current + 1);
+        }
+        self.trajectory.positions_at(self.time, self.interpolation)
+    }
+}
@@ -0,0 +1,294 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use super::parser::{self, ParseError, ParseMode};
+use super::{EventKind, Frame, Frames, Trajectory};
+
+//Binary Format, all numbers little endian
+// magic "PVT\0", version u16, flags u16, body count u32, frame count u64, metadata length u32
+// metadata: the # body and # merge header lines of the text format as UTF-8
+// timestamps: one f64 per frame if FLAG_TIMESTAMPS is set
+// frames: x y z of every body followed by vx vy vz of every body if FLAG_VELOCITIES is set,
+//         stored as f64 if FLAG_F64 is set and as f32 otherwise,
+//         with FLAG_SPARSE bodies that don't exist in a frame are stored as NaN
+
+const MAGIC: [u8; 4] = *b"PVT\0";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 24;
+const FLAG_F64: u16 = 1 << 0;
+const FLAG_TIMESTAMPS: u16 = 1 << 1;
+const FLAG_VELOCITIES: u16 = 1 << 2;
+const FLAG_SPARSE: u16 = 1 << 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+    Single,
+    #[default]
+    Double
+}
+
+#[derive(Debug)]
+pub enum BinaryError {
+    Io(std::io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    Truncated { expected: u64, actual: u64 },
+    Metadata(ParseError)
+}
+
+enum Storage {
+    #[cfg(target_arch = "wasm32")]
+    Bytes(Vec<u8>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Mapped(memmap2::Mmap)
+}
+
+pub(super) struct Packed {
+    storage: Storage,
+    body_count: usize,
+    frame_count: usize,
+    scalar_size: usize,
+    velocities: bool,
+    sparse: bool,
+    timestamps: Option<usize>,
+    frames: usize
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(super) fn read(bytes: Vec<u8>) -> Result<Trajectory, BinaryError> {
+    unpack(Storage::Bytes(bytes))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(super) fn open(path: &str) -> Result<Trajectory, BinaryError> {
+    let file = std::fs::File::open(path).map_err(BinaryError::Io)?;
+    //Safety: the mapping is read only, truncating the file while it is open is not supported
+    let map = unsafe { memmap2::Mmap::map(&file) }.map_err(BinaryError::Io)?;
+    unpack(Storage::Mapped(map))
+}
+
+fn unpack(storage: Storage) -> Result<Trajectory, BinaryError> {
+    let bytes = storage.bytes();
+    let truncated = |expected: u64| BinaryError::Truncated { expected, actual: bytes.len() as u64 };
+    if bytes.len() < HEADER_SIZE {
+        return Err(if bytes.starts_with(&MAGIC) { truncated(HEADER_SIZE as u64) } else { BinaryError::InvalidMagic });
+    }
+
+    if bytes[0..4] != MAGIC {
+        return Err(BinaryError::InvalidMagic);
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(BinaryError::UnsupportedVersion(version));
+    }
+
+    let flags = u16::from_le_bytes([bytes[6], bytes[7]]);
+    let body_count = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as u64;
+    let frame_count = u64::from_le_bytes(bytes[12..20].try_into().unwrap());
+    let metadata_length = u32::from_le_bytes(bytes[20..24].try_into().unwrap()) as u64;
+
+    let scalar_size = if flags & FLAG_F64 != 0 { 8 } else { 4 };
+    let components = if flags & FLAG_VELOCITIES != 0 { 6 } else { 3 };
+    let timestamps_length = if flags & FLAG_TIMESTAMPS != 0 { frame_count.checked_mul(8) } else { Some(0) };
+    let frames_length = frame_count.checked_mul(body_count * components * scalar_size);
+    let expected = timestamps_length.zip(frames_length)
+        .and_then(|(timestamps, frames)| (HEADER_SIZE as u64 + metadata_length).checked_add(timestamps)?.checked_add(frames))
+        .unwrap_or(u64::MAX);
+
+    if (bytes.len() as u64) < expected {
+        return Err(truncated(expected));
+    }
+
+    let metadata = &bytes[HEADER_SIZE..HEADER_SIZE + metadata_length as usize];
+    let metadata = parser::parse(&String::from_utf8_lossy(metadata), ParseMode::Strict).map_err(BinaryError::Metadata)?;
+
+    let timestamps = HEADER_SIZE + metadata_length as usize;
+    let packed = Packed {
+        body_count: body_count as usize,
+        frame_count: frame_count as usize,
+        scalar_size: scalar_size as usize,
+        velocities: flags & FLAG_VELOCITIES != 0,
+        sparse: flags & FLAG_SPARSE != 0,
+        timestamps: (flags & FLAG_TIMESTAMPS != 0).then_some(timestamps),
+        frames: timestamps + timestamps_length.unwrap_or_default() as usize,
+        storage
+    };
+
+    let sparse = packed.sparse;
+    let mut trajectory = Trajectory { bodies: metadata.bodies, simulation: None, events: Vec::new(), frames: Frames::Packed(packed) };
+    //Only sparse files can have bodies appear or disappear
+    if sparse {
+        trajectory.events = trajectory.lifecycle();
+    }
+    for event in metadata.events {
+        if let EventKind::Merge { body, into } = event.kind {
+            trajectory.merge(event.time, body, into);
+        }
+    }
+    Ok(trajectory)
+}
+
+pub(super) fn write(trajectory: &Trajectory, writer: &mut impl Write, precision: Precision) -> std::io::Result<()> {
+    let frame_count = trajectory.len();
+    let frames = || (0..frame_count).filter_map(|index| trajectory.frame(index));
+    //Bodies that appear or disappear are stored as NaN where they don't exist, whether the frames have ids or change their length
+    let mut lengths = frames().map(|frame| frame.positions.len());
+    let first_length = lengths.next();
+    let sparse = frames().any(|frame| frame.ids.is_some()) || lengths.any(|length| Some(length) != first_length);
+    let body_count = if sparse {
+        frames()
+            .flat_map(|frame| (0..frame.positions.len()).map(|entry| frame.body(entry) + 1).max())
+            .fold(trajectory.bodies.len(), usize::max)
+    } else {
+        trajectory.largest_frame().map_or(0, |frame| frame.positions.len())
+    };
+    let timestamps = frame_count > 0 && frames().all(|frame| frame.time.is_some());
+    let velocities = frame_count > 0 && frames().all(|frame| frame.velocities.is_some());
+
+    let mut flags = 0;
+    if precision == Precision::Double {
+        flags |= FLAG_F64;
+    }
+    if timestamps {
+        flags |= FLAG_TIMESTAMPS;
+    }
+    if velocities {
+        flags |= FLAG_VELOCITIES;
+    }
+    if sparse {
+        flags |= FLAG_SPARSE;
+    }
+
+    let mut metadata = trajectory.bodies.iter()
+        .map(|body| format!("{body}\n"))
+        .collect::<String>();
+    let name = |body: usize| trajectory.bodies.get(body).and_then(|body| body.name.as_deref()).map(|name| name.replace(char::is_whitespace, "_"));
+    for event in &trajectory.events {
+        if let EventKind::Merge { body, into } = event.kind {
+            if let (Some(body), Some(into)) = (name(body), name(into)) {
+                metadata.push_str(&format!("# merge {body} {into} at={:e}\n", event.time));
+            }
+        }
+    }
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&flags.to_le_bytes())?;
+    writer.write_all(&(body_count as u32).to_le_bytes())?;
+    writer.write_all(&(frame_count as u64).to_le_bytes())?;
+    writer.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    writer.write_all(metadata.as_bytes())?;
+
+    if timestamps {
+        for frame in frames() {
+            writer.write_all(&frame.time.unwrap_or_default().to_le_bytes())?;
+        }
+    }
+
+    //Frames of a file that isn't sparse all hold every body
+    let mut positions = vec![[0.0; 3]; body_count];
+    let mut velocities = vec![[0.0; 3]; if velocities { body_count } else { 0 }];
+    for frame in frames() {
+        if sparse {
+            positions.fill([f64::NAN; 3]);
+            velocities.fill([f64::NAN; 3]);
+            for entry in 0..frame.positions.len() {
+                positions[frame.body(entry)] = frame.positions[entry];
+                if let (Some(frame_velocities), Some(velocity)) = (&frame.velocities, velocities.get_mut(frame.body(entry))) {
+                    *velocity = frame_velocities[entry];
+                }
+            }
+        } else {
+            positions[..frame.positions.len()].copy_from_slice(&frame.positions);
+            if let Some(frame_velocities) = &frame.velocities {
+                let count = frame_velocities.len().min(velocities.len());
+                velocities[..count].copy_from_slice(&frame_velocities[..count]);
+            }
+        }
+
+        for value in positions.iter().chain(velocities.iter()).flatten() {
+            match precision {
+                Precision::Single => writer.write_all(&(*value as f32).to_le_bytes())?,
+                Precision::Double => writer.write_all(&value.to_le_bytes())?
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+impl Storage {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            #[cfg(target_arch = "wasm32")]
+            Storage::Bytes(bytes) => bytes,
+            #[cfg(not(target_arch = "wasm32"))]
+            Storage::Mapped(map) => map
+        }
+    }
+}
+
+impl Packed {
+    pub(super) fn len(&self) -> usize {
+        self.frame_count
+    }
+
+    pub(super) fn time(&self, index: usize) -> Option<f64> {
+        let offset = self.timestamps?;
+        let start = offset + index * 8;
+        Some(f64::from_le_bytes(self.storage.bytes()[start..start + 8].try_into().unwrap()))
+    }
+
+    pub(super) fn frame(&self, index: usize) -> Option<Frame> {
+        if index >= self.frame_count {
+            return None;
+        }
+
+        let bytes = self.storage.bytes();
+        let time = self.time(index);
+
+        let block = self.body_count * 3 * self.scalar_size;
+        let stride = if self.velocities { block * 2 } else { block };
+        let start = self.frames + index * stride;
+        let mut positions = self.vectors(&bytes[start..start + block]);
+        let mut velocities = self.velocities.then(|| self.vectors(&bytes[start + block..start + stride]));
+        if !self.sparse {
+            return Some(Frame { time, positions, velocities, ids: None });
+        }
+
+        //Drop the bodies that don't exist in this frame
+        let ids = (0..positions.len()).filter(|&body| !positions[body][0].is_nan()).collect::<Vec<_>>();
+        positions = ids.iter().map(|&body| positions[body]).collect();
+        if let Some(values) = &mut velocities {
+            *values = ids.iter().map(|&body| values[body]).collect();
+        }
+        Some(Frame { time, positions, velocities, ids: Some(ids) })
+    }
+
+    fn vectors(&self, bytes: &[u8]) -> Vec<[f64; 3]> {
+        bytes.chunks_exact(3 * self.scalar_size)
+            .map(|vector| {
+                let mut values = vector.chunks_exact(self.scalar_size).map(|scalar| match *scalar {
+                    [a, b, c, d] => f32::from_le_bytes([a, b, c, d]) as f64,
+                    _ => f64::from_le_bytes(scalar.try_into().unwrap())
+                });
+                [(); 3].map(|_| values.next().unwrap_or_default())
+            })
+            .collect()
+    }
+}
+
+impl Display for BinaryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinaryError::Io(err) => write!(f, "{err}"),
+            BinaryError::InvalidMagic => write!(f, "not a binary trajectory file"),
+            BinaryError::UnsupportedVersion(version) => write!(f, "unsupported binary trajectory version {version}"),
+            BinaryError::Truncated { expected, actual } => write!(f, "file is truncated, expected {expected} bytes but found {actual}"),
+            BinaryError::Metadata(err) => write!(f, "metadata {err}")
+        }
+    }
+}
+
+impl Error for BinaryError {}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use super::parser::{ParseError, ParseErrorReason, ParseMode, Parser};
+use super::{Body, Frame, Trajectory};
+
+//Data Format
+// A header row naming the columns, followed by one row per body and time step (long format):
+// t,body,x,y,z,vx,vy,vz
+// Consecutive rows with the same time form one frame and the time must not decrease between rows.
+// The velocity columns are optional, extra columns are ignored.
+// A body missing from the rows of a time step doesn't exist at that time.
+
+#[derive(Clone, Debug)]
+pub struct CsvColumns {
+    pub time: String,
+    pub body: String,
+    pub position: [String; 3],
+    pub velocity: Option<[String; 3]>
+}
+
+impl Default for CsvColumns {
+    fn default() -> CsvColumns {
+        CsvColumns {
+            time: "t".to_string(),
+            body: "body".to_string(),
+            position: ["x", "y", "z"].map(String::from),
+            velocity: Some(["vx", "vy", "vz"].map(String::from))
+        }
+    }
+}
+
+impl CsvColumns {
+    //Column names in the order time,body,x,y,z and optionally vx,vy,vz
+    pub fn parse(names: &str) -> Option<CsvColumns> {
+        let names = names.split(',').map(|name| name.trim().to_string()).collect::<Vec<_>>();
+        match &names[..] {
+            [time, body, x, y, z] => Some(CsvColumns {
+                time: time.clone(),
+                body: body.clone(),
+                position: [x.clone(), y.clone(), z.clone()],
+                velocity: None
+            }),
+            [time, body, x, y, z, vx, vy, vz] => Some(CsvColumns {
+                time: time.clone(),
+                body: body.clone(),
+                position: [x.clone(), y.clone(), z.clone()],
+                velocity: Some([vx.clone(), vy.clone(), vz.clone()])
+            }),
+            _ => None
+        }
+    }
+}
+
+pub(super) fn parse(content: &str, columns: &CsvColumns, mode: ParseMode) -> Result<Trajectory, ParseError> {
+    let mut parser = Parser::new(mode);
+    let mut lines = content.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+
+    let Some((header_index, header)) = lines.next() else {
+        return Ok(Trajectory::default());
+    };
+
+    let header = fields(header);
+    let find = |name: &str| header.iter().position(|&(_, field)| field == name);
+    let require = |name: &str| find(name).ok_or_else(|| ParseError {
+        line: header_index + 1,
+        column: 1,
+        token: name.to_string(),
+        reason: ParseErrorReason::MissingColumn
+    });
+
+    let time_column = require(&columns.time)?;
+    let body_column = require(&columns.body)?;
+    let position_columns = [require(&columns.position[0])?, require(&columns.position[1])?, require(&columns.position[2])?];
+    let velocity_columns = columns.velocity.as_ref()
+        .and_then(|names| Some([find(&names[0])?, find(&names[1])?, find(&names[2])?]));
+    let last_column = position_columns.into_iter()
+        .chain(velocity_columns.into_iter().flatten())
+        .fold(time_column.max(body_column), usize::max);
+
+    let mut ids = HashMap::new();
+    let mut bodies = Vec::new();
+    let mut frames = Vec::<Frame>::new();
+    //Number of the last frame each body appeared in, to find duplicates
+    let mut seen = Vec::new();
+
+    for (index, line) in lines {
+        let fields = fields(line);
+        if fields.len() <= last_column {
+            let column = line.chars().count() + 1;
+            parser.report(ParseError { line: index + 1, column, token: String::new(), reason: ParseErrorReason::MissingField })?;
+            continue;
+        }
+
+        let mut number = |column: usize| {
+            let (column, token) = fields[column];
+            parser.number(index + 1, column, token)
+        };
+
+        let time = number(time_column)?;
+        let position = [number(position_columns[0])?, number(position_columns[1])?, number(position_columns[2])?];
+        let velocity = match velocity_columns {
+            Some(columns) => Some([number(columns[0])?, number(columns[1])?, number(columns[2])?]),
+            None => None
+        };
+
+        let (id_column, id) = fields[body_column];
+        let body = *ids.entry(id.to_string()).or_insert_with(|| {
+            bodies.push(Body { name: Some(id.to_string()), ..Body::default() });
+            seen.push(0);
+            bodies.len() - 1
+        });
+
+        let previous_time = frames.last().and_then(|frame| frame.time);
+        if previous_time != Some(time) {
+            if previous_time.is_some_and(|previous| time < previous) {
+                let (column, token) = fields[time_column];
+                parser.report(ParseError { line: index + 1, column, token: token.to_string(), reason: ParseErrorReason::DecreasingTime })?;
+            }
+
+            frames.push(Frame {
+                time: Some(time),
+                positions: Vec::new(),
+                velocities: velocity.map(|_| Vec::new()),
+                ids: Some(Vec::new())
+            });
+        }
+
+        if seen[body] == frames.len() {
+            parser.report(ParseError { line: index + 1, column: id_column, token: id.to_string(), reason: ParseErrorReason::DuplicateBody })?;
+            continue;
+        }
+        seen[body] = frames.len();
+
+        let frame = frames.last_mut().unwrap();
+        frame.ids.get_or_insert_with(Vec::new).push(body);
+        frame.positions.push(position);
+        if let Some(velocities) = &mut frame.velocities {
+            velocities.push(velocity.unwrap_or_default());
+        }
+    }
+
+    //Bodies missing from a time step are gone from that frame, which makes their births and deaths.
+    //Entries are sorted by body and frames holding every body in order need no ids at all.
+    frames.iter_mut().for_each(sort_entries);
+    if frames.iter().all(|frame| frame.ids.as_ref().is_some_and(|ids| ids.iter().copied().eq(0..bodies.len()))) {
+        for frame in &mut frames {
+            frame.ids = None;
+        }
+    }
+
+    parser.finish();
+    Ok(Trajectory::new(bodies, frames))
+}
+
+fn sort_entries(frame: &mut Frame) {
+    let Some(ids) = &frame.ids else { return };
+    let mut order = (0..ids.len()).collect::<Vec<_>>();
+    order.sort_unstable_by_key(|&entry| ids[entry]);
+    frame.ids = Some(order.iter().map(|&entry| ids[entry]).collect());
+    frame.positions = order.iter().map(|&entry| frame.positions[entry]).collect();
+    if let Some(velocities) = &mut frame.velocities {
+        *velocities = order.iter().map(|&entry| velocities[entry]).collect();
+    }
+}
+
+fn fields(line: &str) -> Vec<(usize, &str)> {
+    let mut column = 1;
+    line.split(',')
+        .map(|field| {
+            let indent = field.chars().take_while(|c| c.is_whitespace()).count();
+            let start = column + indent;
+            column += field.chars().count() + 1;
+            (start, field.trim().trim_matches('"'))
+        })
+        .collect()
+}
@@ -0,0 +1,184 @@
+use crate::physics::{Elements, GRAVITATIONAL_CONSTANT};
+use super::parser::{self, ParseError, ParseErrorReason, ParseMode, Parser};
+use super::{Frame, Trajectory};
+
+//Data Format
+// # central <name> mass=<kg> [radius=<m>] [color=#rrggbb] [light=<intensity>] [texture=<image>]   required, the body every orbit is around, it stays in the origin
+// # epoch <s>   optional, time the mean anomalies refer to, 0 by default
+// # span start=<s> end=<s> step=<s>   optional, frames to generate, one orbit of the outermost body by default,
+//   at most 100000 frames
+// <name> a e i Ω ω M [mass=<kg>] [radius=<m>] [color=#rrggbb] [light=<intensity>] [texture=<image>]   one line per body of a bound orbit,
+//   semi-major axis in m and angles in degrees
+// Every body follows its Kepler orbit around the central body, they don't attract each other.
+
+//Default number of frames per orbit of the innermost body if no span is given
+const STEPS_PER_ORBIT: f64 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start: f64,
+    pub end: f64,
+    pub step: f64
+}
+
+impl Span {
+    //Upper bound for the generated frames, a tiny step would otherwise exhaust the memory
+    pub const MAX_FRAMES: usize = 100_000;
+
+    //Parses start,end,step
+    pub fn parse(text: &str) -> Option<Span> {
+        let values = text.split(',').map(|value| value.trim().parse::<f64>().ok()).collect::<Option<Vec<_>>>()?;
+        match values[..] {
+            [start, end, step] if step > 0.0 && end >= start => {
+                Some(Span { start, end, step }).filter(|span| span.frames() <= Span::MAX_FRAMES)
+            }
+            _ => None
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        ((self.end - self.start) / self.step).floor() as usize + 1
+    }
+
+    //The same span with the step raised so it fits into the maximum number of frames
+    fn limited(self) -> Span {
+        let step = self.step.max((self.end - self.start) / (Span::MAX_FRAMES - 1) as f64);
+        Span { step, ..self }
+    }
+}
+
+//The span argument takes precedence over a # span line
+pub(super) fn parse(content: &str, span: Option<Span>, mode: ParseMode) -> Result<Trajectory, ParseError> {
+    let mut parser = Parser::new(mode);
+    let mut central = None;
+    let mut epoch = 0.0;
+    let mut declared_span = None;
+    let mut bodies = Vec::new();
+    let mut orbits = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        if let Some(header) = line.trim_start().strip_prefix('#') {
+            let indent = line.chars().count() - header.chars().count();
+            let tokens = parser::tokens(header).map(|(column, token)| (column + indent, token)).collect::<Vec<_>>();
+            match &tokens[..] {
+                [(column, "central"), attributes @ ..] => {
+                    let body = parser.body(index + 1, line, attributes)?;
+                    if body.mass.is_none() {
+                        parser.report(ParseError { line: index + 1, column: *column, token: "mass".to_string(), reason: ParseErrorReason::MissingAttribute })?;
+                    }
+                    central = Some(body);
+                }
+                [(_, "epoch"), (column, value)] => epoch = parser.number(index + 1, *column, value)?,
+                [(column, "span"), attributes @ ..] => declared_span = span_directive(&mut parser, index + 1, *column, attributes)?,
+                _ => ()
+            }
+            continue;
+        }
+
+        let tokens = parser::tokens(line).collect::<Vec<_>>();
+        if tokens.is_empty() {
+            continue;
+        }
+
+        if tokens.len() < 7 {
+            let column = tokens.last().map_or(1, |&(column, token)| column + token.chars().count());
+            parser.report(ParseError { line: index + 1, column, token: String::new(), reason: ParseErrorReason::MissingField })?;
+            continue;
+        }
+
+        let mut values = [0.0; 6];
+        for (value, &(column, token)) in values.iter_mut().zip(&tokens[1..7]) {
+            *value = parser.number(index + 1, column, token)?;
+        }
+
+        let [semi_major_axis, eccentricity, inclination, ascending_node, argument_of_periapsis, mean_anomaly] = values;
+        if semi_major_axis <= 0.0 || !(0.0..1.0).contains(&eccentricity) {
+            let (column, token) = tokens[if semi_major_axis <= 0.0 { 1 } else { 2 }];
+            parser.report(ParseError { line: index + 1, column, token: token.to_string(), reason: ParseErrorReason::UnboundOrbit })?;
+            continue;
+        }
+
+        let body_tokens = tokens[..1].iter().chain(&tokens[7..]).copied().collect::<Vec<_>>();
+        bodies.push(parser.body(index + 1, line, &body_tokens)?);
+        orbits.push(Elements {
+            semi_major_axis,
+            eccentricity,
+            inclination: inclination.to_radians(),
+            ascending_node: ascending_node.to_radians(),
+            argument_of_periapsis: argument_of_periapsis.to_radians(),
+            mean_anomaly: mean_anomaly.to_radians()
+        });
+    }
+
+    let Some(central) = central else {
+        let error = ParseError { line: content.lines().count() + 1, column: 1, token: String::new(), reason: ParseErrorReason::MissingCentralBody };
+        parser.report(error)?;
+        parser.finish();
+        return Ok(Trajectory::new(bodies, Vec::new()));
+    };
+
+    let central_mass = central.mass.unwrap_or_default();
+    let mus = bodies.iter()
+        .map(|body| GRAVITATIONAL_CONSTANT * (central_mass + body.mass.unwrap_or_default()))
+        .collect::<Vec<_>>();
+    let periods = orbits.iter().zip(&mus)
+        .map(|(orbit, mu)| orbit.period(*mu))
+        .filter(|period| period.is_finite())
+        .collect::<Vec<_>>();
+
+    let span = span.or(declared_span).unwrap_or_else(|| {
+        let longest = periods.iter().copied().fold(0.0, f64::max);
+        let shortest = periods.iter().copied().fold(f64::INFINITY, f64::min);
+        let step = if shortest.is_finite() { shortest / STEPS_PER_ORBIT } else { 1.0 };
+        Span { start: epoch, end: epoch + longest, step }.limited()
+    });
+
+    let frames = (0..span.frames())
+        .map(|index| {
+            let time = span.start + index as f64 * span.step;
+            let (positions, velocities) = std::iter::once(([0.0; 3], [0.0; 3]))
+                .chain(orbits.iter().zip(&mus).map(|(orbit, &mu)| orbit.propagate(mu, time - epoch).to_state(mu)))
+                .unzip();
+            Frame { time: Some(time), positions, velocities: Some(velocities), ids: None }
+        })
+        .collect();
+
+    parser.finish();
+    Ok(Trajectory::new(std::iter::once(central).chain(bodies).collect(), frames))
+}
+
+fn span_directive(parser: &mut Parser, line: usize, column: usize, tokens: &[(usize, &str)]) -> Result<Option<Span>, ParseError> {
+    let (mut start, mut end, mut step) = (None, None, None);
+    for (column, key, value_column, value) in parser.attributes(line, tokens)? {
+        match key {
+            "start" => start = Some(parser.number(line, value_column, value)?),
+            "end" => end = Some(parser.number(line, value_column, value)?),
+            "step" => {
+                let value = parser.number(line, value_column, value)?;
+                if value > 0.0 {
+                    step = Some(value);
+                } else {
+                    parser.report(ParseError { line, column: value_column, token: value.to_string(), reason: ParseErrorReason::NonPositiveStep })?;
+                }
+            }
+            _ => parser.report(ParseError { line, column, token: key.to_string(), reason: ParseErrorReason::UnknownAttribute })?
+        }
+    }
+
+    for (name, value) in [("start", start), ("end", end), ("step", step)] {
+        if value.is_none() {
+            parser.report(ParseError { line, column, token: name.to_string(), reason: ParseErrorReason::MissingAttribute })?;
+        }
+    }
+
+    let Some(span) = start.zip(end).zip(step).map(|((start, end), step)| Span { start, end, step }).filter(|span| span.end >= span.start) else {
+        return Ok(None);
+    };
+
+    //Lenient mode raises the step instead
+    if span.frames() > Span::MAX_FRAMES {
+        parser.report(ParseError { line, column, token: span.frames().to_string(), reason: ParseErrorReason::TooManyFrames })?;
+        return Ok(Some(span.limited()));
+    }
+    Ok(Some(span))
+}
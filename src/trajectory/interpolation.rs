@@ -0,0 +1,128 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use super::{Frame, Trajectory};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+    #[default]
+    Linear,
+    //Cubic Hermite if both frames carry timestamps and velocities, Catmull-Rom otherwise
+    Cubic
+}
+
+impl Interpolation {
+    pub fn next(self) -> Interpolation {
+        match self {
+            Interpolation::Nearest => Interpolation::Linear,
+            Interpolation::Linear => Interpolation::Cubic,
+            Interpolation::Cubic => Interpolation::Nearest
+        }
+    }
+}
+
+//Bodies follow the frame at or before the time, bodies missing from the next frame hold their position
+pub(super) fn positions_at(trajectory: &Trajectory, time: f64, interpolation: Interpolation) -> Frame {
+    let index = trajectory.frame_at(time);
+    let Some(from) = trajectory.frame(index) else {
+        return Frame::default();
+    };
+
+    let snapshot = |positions: Vec<[f64; 3]>, ids: Option<Vec<usize>>| Frame { time: Some(time), positions, velocities: None, ids };
+    let (Some(t0), Some(t1), Some(to)) = (trajectory.time(index), trajectory.time(index + 1), trajectory.frame(index + 1)) else {
+        return snapshot(from.positions.clone(), from.ids.clone());
+    };
+
+    let u = if t1 > t0 { ((time - t0) / (t1 - t0)).clamp(0.0, 1.0) } else { 0.0 };
+    if interpolation == Interpolation::Nearest {
+        let nearest = if u < 0.5 { &from } else { &to };
+        return snapshot(nearest.positions.clone(), nearest.ids.clone());
+    }
+
+    let mut positions = from.positions.clone();
+    let target = align(&to.positions, &to, &from, &from.positions);
+    if interpolation == Interpolation::Linear {
+        for (position, (p0, p1)) in positions.iter_mut().zip(from.positions.iter().zip(target.iter())) {
+            *position = [0, 1, 2].map(|axis| p0[axis] + (p1[axis] - p0[axis]) * u);
+        }
+        return snapshot(positions, from.ids.clone());
+    }
+
+    let dt = t1 - t0;
+    let (m0, m1) = match (&from.velocities, &to.velocities, from.time.is_some() && to.time.is_some()) {
+        (Some(v0), Some(v1), true) => (Cow::Borrowed(&v0[..]), align(v1, &to, &from, v0)),
+        _ => (
+            Cow::Owned(tangents(trajectory, index, &from, &from)),
+            Cow::Owned(tangents(trajectory, index + 1, &to, &from))
+        )
+    };
+
+    for entry in 0..positions.len().min(m0.len()).min(m1.len()) {
+        let (p0, p1) = (from.positions[entry], target[entry]);
+        let (v0, v1) = (m0[entry], m1[entry]);
+        positions[entry] = [0, 1, 2].map(|axis| hermite(p0[axis], v0[axis] * dt, p1[axis], v1[axis] * dt, u));
+    }
+
+    snapshot(positions, from.ids.clone())
+}
+
+//Reorders the values of a frame to the entries of the reference frame, bodies missing from the frame keep the fallback
+fn align<'a>(values: &'a [[f64; 3]], frame: &Frame, reference: &Frame, fallback: &[[f64; 3]]) -> Cow<'a, [[f64; 3]]> {
+    if frame.ids == reference.ids && values.len() >= fallback.len() {
+        return Cow::Borrowed(values);
+    }
+
+    if frame.ids.is_none() && reference.ids.is_none() {
+        return Cow::Owned((0..fallback.len()).map(|entry| values.get(entry).copied().unwrap_or(fallback[entry])).collect());
+    }
+
+    let entries = (0..values.len()).map(|entry| (frame.body(entry), entry)).collect::<HashMap<_, _>>();
+    Cow::Owned((0..fallback.len())
+        .map(|entry| entries.get(&reference.body(entry)).map_or(fallback[entry], |&entry| values[entry]))
+        .collect())
+}
+
+//Finite difference velocities for Catmull-Rom splines over non-uniformly spaced frames, in the entry order of the reference
+pub(super) fn tangents(trajectory: &Trajectory, index: usize, frame: &Frame, reference: &Frame) -> Vec<[f64; 3]> {
+    let count = reference.positions.len();
+    let here = align(&frame.positions, frame, reference, &reference.positions).into_owned();
+    let neighbour = |index: usize| {
+        let frame = trajectory.frame(index)?;
+        Some((trajectory.time(index)?, align(&frame.positions, &frame, reference, &here).into_owned()))
+    };
+    let previous = index.checked_sub(1).and_then(neighbour);
+    let next = neighbour(index + 1);
+    let time = trajectory.time(index).unwrap_or_default();
+
+    let (before, after) = match (&previous, &next) {
+        (Some((t0, p0)), Some((t1, p1))) => ((*t0, p0), (*t1, p1)),
+        (Some((t0, p0)), None) => ((*t0, p0), (time, &here)),
+        (None, Some((t1, p1))) => ((time, &here), (*t1, p1)),
+        (None, None) => return vec![[0.0; 3]; count]
+    };
+
+    let dt = after.0 - before.0;
+    (0..count)
+        .map(|entry| match (before.1.get(entry), after.1.get(entry)) {
+            (Some(p0), Some(p1)) if dt > 0.0 => [0, 1, 2].map(|axis| (p1[axis] - p0[axis]) / dt),
+            _ => [0.0; 3]
+        })
+        .collect()
+}
+
+fn hermite(p0: f64, m0: f64, p1: f64, m1: f64, u: f64) -> f64 {
+    let u2 = u * u;
+    let u3 = u2 * u;
+    (2.0 * u3 - 3.0 * u2 + 1.0) * p0 + (u3 - 2.0 * u2 + u) * m0 + (-2.0 * u3 + 3.0 * u2) * p1 + (u3 - u2) * m1
+}
+
+impl Display for Interpolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interpolation::Nearest => write!(f, "nearest"),
+            Interpolation::Linear => write!(f, "linear"),
+            Interpolation::Cubic => write!(f, "cubic")
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use binary::Packed;
+use crate::physics::SimulationSettings;
+
+mod binary;
+mod csv;
+mod elements;
+mod interpolation;
+mod parser;
+
+pub use binary::{BinaryError, Precision};
+pub use csv::CsvColumns;
+pub use elements::Span;
+pub use interpolation::Interpolation;
+pub use parser::{ParseError, ParseMode};
+
+#[derive(Default)]
+pub struct Trajectory {
+    pub bodies: Vec<Body>,
+    //Continue the trajectory with a live simulation from its last frame
+    pub simulation: Option<SimulationSettings>,
+    //Births, deaths and merges of bodies in the order they happen
+    pub events: Vec<Event>,
+    frames: Frames,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Body {
+    pub name: Option<String>,
+    pub mass: Option<f64>,
+    pub radius: Option<f64>,
+    pub color: Option<[f32; 4]>,
+    //Brightness of a body that lights the others, 1 for a star like the Sun
+    pub light: Option<f64>,
+    //Name of an equirectangular image wrapped around the body
+    pub texture: Option<String>,
+}
+
+#[derive(Clone, Default)]
+pub struct Frame {
+    pub time: Option<f64>,
+    pub positions: Vec<[f64; 3]>,
+    pub velocities: Option<Vec<[f64; 3]>>,
+    //Index into the bodies for every entry, without ids entry i is body i
+    pub ids: Option<Vec<usize>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Event {
+    pub time: f64,
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Birth(usize),
+    Death(usize),
+    //The body ends by merging into another one
+    Merge { body: usize, into: usize },
+    //Closest approach of two bodies below the encounter distance
+    Encounter { body: usize, other: usize, distance: f64 },
+    //Two bodies start to overlap
+    Collision { body: usize, other: usize },
+    Periapsis { body: usize, primary: usize, distance: f64 },
+    Apoapsis { body: usize, primary: usize, distance: f64 },
+    //The body passes through the plane z = 0
+    PlaneCrossing { body: usize, ascending: bool }
+}
+
+//Parsed text formats own their frames, the binary format is decoded frame by frame on access
+enum Frames {
+    Owned(Vec<Frame>),
+    Packed(Packed)
+}
+
+impl Default for Frames {
+    fn default() -> Frames {
+        Frames::Owned(Vec::new())
+    }
+}
+
+impl Trajectory {
+    pub fn new(bodies: Vec<Body>, frames: Vec<Frame>) -> Trajectory {
+        let mut trajectory = Trajectory { bodies, simulation: None, events: Vec::new(), frames: Frames::Owned(frames) };
+        trajectory.events = trajectory.lifecycle();
+        trajectory
+    }
+
+    pub fn parse(content: &str, mode: ParseMode) -> Result<Trajectory, ParseError> {
+        parser::parse(content, mode)
+    }
+
+    pub fn parse_csv(content: &str, columns: &CsvColumns, mode: ParseMode) -> Result<Trajectory, ParseError> {
+        csv::parse(content, columns, mode)
+    }
+
+    //Frames of bodies on Kepler orbits around a central body, the span defaults to the one in the content
+    pub fn parse_elements(content: &str, span: Option<Span>, mode: ParseMode) -> Result<Trajectory, ParseError> {
+        elements::parse(content, span, mode)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_binary(bytes: Vec<u8>) -> Result<Trajectory, BinaryError> {
+        binary::read(bytes)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_binary(path: &str) -> Result<Trajectory, BinaryError> {
+        binary::open(path)
+    }
+
+    pub fn write_binary(&self, writer: &mut impl std::io::Write, precision: Precision) -> std::io::Result<()> {
+        binary::write(self, writer, precision)
+    }
+
+    pub fn write_text(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        parser::write(self, writer)
+    }
+
+    //Only parsed trajectories can grow, packed frames are read only and reject the frame
+    pub fn push(&mut self, frame: Frame) -> bool {
+        match &mut self.frames {
+            Frames::Owned(frames) => {
+                frames.push(frame);
+                true
+            }
+            Frames::Packed(_) => false
+        }
+    }
+
+    //Drops every other frame but the first, counting back from the last one so the spacing at the end stays even.
+    //Keeps a trajectory that grows without end within a memory budget, packed frames are left alone.
+    pub fn decimate(&mut self) {
+        if let Frames::Owned(frames) = &mut self.frames {
+            let last = frames.len().saturating_sub(1);
+            let mut index = 0;
+            frames.retain(|_| {
+                let keep = index == 0 || (last - index) % 2 == 0;
+                index += 1;
+                keep
+            });
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.frames {
+            Frames::Owned(frames) => frames.len(),
+            Frames::Packed(packed) => packed.len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn frame(&self, index: usize) -> Option<Cow<'_, Frame>> {
+        match &self.frames {
+            Frames::Owned(frames) => frames.get(index).map(Cow::Borrowed),
+            Frames::Packed(packed) => packed.frame(index).map(Cow::Owned)
+        }
+    }
+
+    //Simulation time of a frame, frames without a timestamp are one time unit apart
+    pub fn time(&self, index: usize) -> Option<f64> {
+        match &self.frames {
+            Frames::Owned(frames) => frames.get(index).map(|frame| frame.time.unwrap_or(index as f64)),
+            Frames::Packed(packed) => (index < packed.len()).then(|| packed.time(index).unwrap_or(index as f64))
+        }
+    }
+
+    //Index of the last frame at or before the given time
+    pub fn frame_at(&self, time: f64) -> usize {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.time(middle).is_some_and(|middle_time| middle_time <= time) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        low.saturating_sub(1)
+    }
+
+    //The bodies that exist at the given time and where they are
+    pub fn positions_at(&self, time: f64, interpolation: Interpolation) -> Frame {
+        interpolation::positions_at(self, time, interpolation)
+    }
+
+    //Velocities of a frame, finite-differenced from the neighbouring frames if it doesn't carry them
+    pub fn velocities(&self, index: usize) -> Option<Vec<[f64; 3]>> {
+        let frame = self.frame(index)?;
+        match &frame.velocities {
+            Some(velocities) => Some(velocities.clone()),
+            None => Some(interpolation::tangents(self, index, &frame, &frame))
+        }
+    }
+
+    pub fn start_time(&self) -> f64 {
+        self.time(0).unwrap_or_default()
+    }
+
+    pub fn end_time(&self) -> f64 {
+        self.time(self.len().saturating_sub(1)).unwrap_or_default()
+    }
+
+    pub fn average_step(&self) -> f64 {
+        match self.len() {
+            0 | 1 => 1.0,
+            len => Some((self.end_time() - self.start_time()) / (len - 1) as f64)
+                .filter(|&step| step > 0.0)
+                .unwrap_or(1.0)
+        }
+    }
+
+    //The frame containing the most bodies, the first one if several share the maximum
+    pub fn largest_frame(&self) -> Option<Cow<'_, Frame>> {
+        match &self.frames {
+            Frames::Owned(frames) => frames.iter()
+                .reduce(|largest, frame| if frame.positions.len() > largest.positions.len() { frame } else { largest })
+                .map(Cow::Borrowed),
+            Frames::Packed(packed) => packed.frame(0).map(Cow::Owned)
+        }
+    }
+
+    //Records a merge, replacing the first death of the body at or after that time,
+    //the merge happens where the body disappears from the frames
+    pub fn merge(&mut self, time: f64, body: usize, into: usize) {
+        let death = self.events.iter().position(|event| event.time >= time && event.kind == EventKind::Death(body));
+        let time = death.map_or(time, |death| self.events.remove(death).time);
+        self.record(Event { time, kind: EventKind::Merge { body, into } });
+    }
+
+    //Adds an event after the ones at the same time
+    pub fn record(&mut self, event: Event) {
+        let index = self.events.partition_point(|other| other.time <= event.time);
+        self.events.insert(index, event);
+    }
+
+    //First event after the given time
+    pub fn next_event(&self, time: f64) -> Option<&Event> {
+        self.events.get(self.events.partition_point(|event| event.time <= time))
+    }
+
+    //Last event before the given time
+    pub fn previous_event(&self, time: f64) -> Option<&Event> {
+        self.events[..self.events.partition_point(|event| event.time < time)].last()
+    }
+
+    //Writes the events as CSV with the names of the bodies, the value is the distance where there is one
+    pub fn write_events(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        writeln!(writer, "time,event,body,other,value")?;
+        for event in &self.events {
+            let (kind, body, other, value) = event.kind.fields();
+            let other = other.map_or(String::new(), |other| self.name(other));
+            let value = value.map_or(String::new(), |value| value.to_string());
+            writeln!(writer, "{},{kind},{},{other},{value}", event.time, self.name(body))?;
+        }
+        writer.flush()
+    }
+
+    //Event in words, for the log
+    pub fn describe(&self, event: &Event) -> String {
+        let (kind, body, other, value) = event.kind.fields();
+        let mut description = format!("t={:e}: {kind} of {}", event.time, self.name(body));
+        if let Some(other) = other {
+            let relation = match event.kind {
+                EventKind::Merge { .. } => "into",
+                EventKind::Periapsis { .. } | EventKind::Apoapsis { .. } => "around",
+                _ => "and"
+            };
+            description.push_str(&format!(" {relation} {}", self.name(other)));
+        }
+        if let Some(value) = value {
+            description.push_str(&format!(" at {value:e}"));
+        }
+        description
+    }
+
+    //Name of a body or its index if it has none
+    pub fn name(&self, body: usize) -> String {
+        self.bodies.get(body).and_then(|body| body.name.clone()).unwrap_or_else(|| format!("body {body}"))
+    }
+
+    //Births and deaths from bodies appearing in or disappearing from consecutive frames
+    fn lifecycle(&self) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut previous = HashSet::new();
+        let mut previous_count = None;
+        for index in 0..self.len() {
+            let (Some(frame), Some(time)) = (self.frame(index), self.time(index)) else { continue };
+            //Frames without ids only change bodies when their length changes
+            if frame.ids.is_none() && previous_count == Some(frame.positions.len()) {
+                continue;
+            }
+            previous_count = frame.ids.is_none().then_some(frame.positions.len());
+
+            let current = (0..frame.positions.len()).map(|entry| frame.body(entry)).collect::<HashSet<_>>();
+            if index > 0 {
+                let mut born = current.difference(&previous).copied().collect::<Vec<_>>();
+                let mut died = previous.difference(&current).copied().collect::<Vec<_>>();
+                born.sort_unstable();
+                died.sort_unstable();
+                events.extend(died.into_iter().map(|body| Event { time, kind: EventKind::Death(body) }));
+                events.extend(born.into_iter().map(|body| Event { time, kind: EventKind::Birth(body) }));
+            }
+            previous = current;
+        }
+        events
+    }
+}
+
+impl EventKind {
+    //Name of the kind, the body, the body it relates to and the distance
+    fn fields(&self) -> (&'static str, usize, Option<usize>, Option<f64>) {
+        match *self {
+            EventKind::Birth(body) => ("birth", body, None, None),
+            EventKind::Death(body) => ("death", body, None, None),
+            EventKind::Merge { body, into } => ("merge", body, Some(into), None),
+            EventKind::Encounter { body, other, distance } => ("encounter", body, Some(other), Some(distance)),
+            EventKind::Collision { body, other } => ("collision", body, Some(other), None),
+            EventKind::Periapsis { body, primary, distance } => ("periapsis", body, Some(primary), Some(distance)),
+            EventKind::Apoapsis { body, primary, distance } => ("apoapsis", body, Some(primary), Some(distance)),
+            EventKind::PlaneCrossing { body, ascending: true } => ("ascending node", body, None, None),
+            EventKind::PlaneCrossing { body, ascending: false } => ("descending node", body, None, None)
+        }
+    }
+}
+
+impl Frame {
+    //Body index of an entry
+    pub fn body(&self, entry: usize) -> usize {
+        self.ids.as_ref().map_or(entry, |ids| ids[entry])
+    }
+}
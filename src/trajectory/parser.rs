@@ -0,0 +1,471 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use log::warn;
+use crate::physics::{ForceEvaluator, Integrator, SimulationSettings};
+use super::{Body, EventKind, Frame, Span, Trajectory};
+
+//Data Format
+// # body <name> [mass=<kg>] [radius=<m>] [color=#rrggbb] [light=<intensity>] [texture=<image>]   optional, one line per planet in order,
+//   - as name for none, bodies with a light illuminate the others, a texture is a PNG or JPEG file next to the data
+// # time   optional, every line starts with the simulation time of the frame, which must not decrease
+// # velocities   optional, every planet is followed by its velocity vx vy vz
+// # ids   optional, every planet starts with its id, which is the name of a # body line or a new body,
+//   so the planets in a frame may change
+// # merge <id> <into> [at=<time>]   the first body merges into the second at the given time or with the next frame
+// # simulate step=<s> [G=<constant>] [softening=<m>] [integrator=leapfrog|rk45] [tolerance=<relative error>] [opening_angle=<rad>]
+//   optional, continues with a live simulation after the last line, for rk45 the step is only the initial guess,
+//   an opening angle switches from direct summation to the Barnes-Hut tree
+// x y z x1 y1 z1 ... for every planet followed by new lines and the new cooridnates for the next time step
+// Blank lines and other lines starting with # are ignored. In strict mode the first malformed token aborts the whole file,
+// in lenient mode it is reported as a warning and replaced by 0.0.
+
+const MAX_WARNINGS: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    #[default]
+    Strict,
+    Lenient
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorReason {
+    InvalidNumber,
+    NonFiniteNumber,
+    IncompleteEntry,
+    MissingName,
+    UnknownAttribute,
+    InvalidColor,
+    MissingColumn,
+    MissingField,
+    DuplicateBody,
+    DecreasingTime,
+    LateDirective,
+    UnknownBody,
+    MissingAttribute,
+    NonPositiveStep,
+    NonPositiveTolerance,
+    TooManyFrames,
+    MissingVelocities,
+    UnknownIntegrator,
+    UnboundOrbit,
+    MissingCentralBody
+}
+
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub reason: ParseErrorReason
+}
+
+pub(super) struct Parser {
+    mode: ParseMode,
+    warnings: usize
+}
+
+pub(super) fn parse(content: &str, mode: ParseMode) -> Result<Trajectory, ParseError> {
+    let mut parser = Parser::new(mode);
+    let mut bodies = Vec::new();
+    let mut frames = Vec::<Frame>::new();
+    let mut simulation = None;
+    let mut simulate_line = (0, 0);
+    let mut timed = false;
+    let mut with_velocities = false;
+    let mut with_ids = false;
+    //Body index by name and merges as (frame index or time, body, into)
+    let mut names = HashMap::new();
+    let mut merges = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        if let Some(header) = line.trim_start().strip_prefix('#') {
+            let indent = line.chars().count() - header.chars().count();
+            let tokens = tokens(header).map(|(column, token)| (column + indent, token)).collect::<Vec<_>>();
+            match &tokens[..] {
+                [(_, "body"), attributes @ ..] => {
+                    let body = parser.body(index + 1, line, attributes)?;
+                    if let Some(name) = &body.name {
+                        names.entry(name.clone()).or_insert(bodies.len());
+                    }
+                    bodies.push(body);
+                }
+                [(column, "simulate"), attributes @ ..] => {
+                    simulation = parser.simulation(index + 1, *column, attributes)?;
+                    simulate_line = (index + 1, *column);
+                }
+                [(_, "merge"), (body_column, body), (into_column, into), attributes @ ..] => {
+                    let known = |parser: &mut Parser, column: usize, name: &str| -> Result<Option<usize>, ParseError> {
+                        let body = names.get(name).copied();
+                        if body.is_none() {
+                            parser.report(ParseError { line: index + 1, column, token: name.to_string(), reason: ParseErrorReason::UnknownBody })?;
+                        }
+                        Ok(body)
+                    };
+                    let (body, into) = (known(&mut parser, *body_column, body)?, known(&mut parser, *into_column, into)?);
+                    let mut time = None;
+                    for (column, key, value_column, value) in parser.attributes(index + 1, attributes)? {
+                        match key {
+                            "at" => time = Some(parser.number(index + 1, value_column, value)?),
+                            _ => parser.report(ParseError { line: index + 1, column, token: key.to_string(), reason: ParseErrorReason::UnknownAttribute })?
+                        }
+                    }
+                    if let (Some(body), Some(into)) = (body, into) {
+                        merges.push((time.ok_or(frames.len()), body, into));
+                    }
+                }
+                [(column, directive @ ("time" | "velocities" | "ids"))] if !frames.is_empty() => {
+                    parser.report(ParseError { line: index + 1, column: *column, token: directive.to_string(), reason: ParseErrorReason::LateDirective })?;
+                }
+                [(_, "time")] => timed = true,
+                [(_, "velocities")] => with_velocities = true,
+                [(_, "ids")] => with_ids = true,
+                _ => ()
+            }
+            continue;
+        }
+
+        let mut tokens = tokens(line).collect::<Vec<_>>();
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let time = if timed {
+            let (column, token) = tokens.remove(0);
+            let time = parser.number(index + 1, column, token)?;
+            if frames.last().and_then(|frame| frame.time).is_some_and(|previous| time < previous) {
+                parser.report(ParseError { line: index + 1, column, token: token.to_string(), reason: ParseErrorReason::DecreasingTime })?;
+            }
+            Some(time)
+        } else {
+            None
+        };
+
+        let size = if with_velocities { 6 } else { 3 } + with_ids as usize;
+        let mut positions = Vec::with_capacity(tokens.len() / size);
+        let mut velocities = Vec::with_capacity(if with_velocities { tokens.len() / size } else { 0 });
+        let mut ids = Vec::with_capacity(if with_ids { tokens.len() / size } else { 0 });
+        let mut seen = HashSet::new();
+        for chunk in tokens.chunks(size) {
+            let entry = if with_ids {
+                let (column, name) = chunk[0];
+                let body = *names.entry(name.to_string()).or_insert_with(|| {
+                    bodies.push(Body { name: Some(name.to_string()), ..Body::default() });
+                    bodies.len() - 1
+                });
+                if !seen.insert(body) {
+                    parser.report(ParseError { line: index + 1, column, token: name.to_string(), reason: ParseErrorReason::DuplicateBody })?;
+                }
+                ids.push(body);
+                &chunk[1..]
+            } else {
+                chunk
+            };
+
+            let mut values = [0.0; 6];
+            for (value, &(column, token)) in values.iter_mut().zip(entry) {
+                *value = parser.number(index + 1, column, token)?;
+            }
+
+            //Reported where the entry starts, at its id if it has one, also if nothing follows the id
+            if chunk.len() < size {
+                parser.report(ParseError {
+                    line: index + 1,
+                    column: chunk[0].0,
+                    token: chunk.iter().map(|&(_, token)| token).collect::<Vec<_>>().join(" "),
+                    reason: ParseErrorReason::IncompleteEntry
+                })?;
+            }
+
+            positions.push([values[0], values[1], values[2]]);
+            if with_velocities {
+                velocities.push([values[3], values[4], values[5]]);
+            }
+        }
+
+        let velocities = with_velocities.then_some(velocities);
+        let ids = with_ids.then_some(ids);
+        frames.push(Frame { time, positions, velocities, ids });
+    }
+
+    //The simulation starts from the velocities of the last frame, differenced from the frames before it if they have times
+    let startable = frames.last().is_none_or(|frame| frame.velocities.is_some() || frame.time.is_some() && frames.len() > 1);
+    if simulation.is_some() && !startable {
+        let (line, column) = simulate_line;
+        parser.report(ParseError { line, column, token: String::from("simulate"), reason: ParseErrorReason::MissingVelocities })?;
+    }
+
+    parser.finish();
+    let mut trajectory = Trajectory::new(bodies, frames);
+    trajectory.simulation = simulation;
+    for (time, body, into) in merges {
+        let time = time.unwrap_or_else(|frame| trajectory.time(frame).unwrap_or(trajectory.end_time()));
+        trajectory.merge(time, body, into);
+    }
+    Ok(trajectory)
+}
+
+pub(super) fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.split_whitespace().map(move |token| {
+        let offset = token.as_ptr() as usize - line.as_ptr() as usize;
+        (line[..offset].chars().count() + 1, token)
+    })
+}
+
+impl Parser {
+    pub(super) fn new(mode: ParseMode) -> Parser {
+        Parser { mode, warnings: 0 }
+    }
+
+    pub(super) fn finish(self) {
+        if self.warnings > MAX_WARNINGS {
+            warn!("{} further problems were ignored", self.warnings - MAX_WARNINGS);
+        }
+    }
+
+    pub(super) fn body(&mut self, line: usize, text: &str, tokens: &[(usize, &str)]) -> Result<Body, ParseError> {
+        let mut body = Body::default();
+        let attributes = match tokens.split_first() {
+            Some((&(_, name), attributes)) if !name.contains('=') => {
+                body.name = Some(name.to_string()).filter(|name| name != "-");
+                attributes
+            }
+            _ => {
+                let column = tokens.first().map_or(text.chars().count() + 1, |&(column, _)| column);
+                self.report(ParseError { line, column, token: String::new(), reason: ParseErrorReason::MissingName })?;
+                tokens
+            }
+        };
+
+        for (column, key, value_column, value) in self.attributes(line, attributes)? {
+            match key {
+                "mass" => body.mass = Some(self.number(line, value_column, value)?),
+                "radius" => body.radius = Some(self.number(line, value_column, value)?),
+                "light" => body.light = Some(self.number(line, value_column, value)?),
+                "texture" => body.texture = Some(value.to_string()),
+                "color" => match parse_color(value) {
+                    Some(color) => body.color = Some(color),
+                    None => self.report(ParseError { line, column: value_column, token: value.to_string(), reason: ParseErrorReason::InvalidColor })?
+                },
+                _ => self.report(ParseError { line, column, token: key.to_string(), reason: ParseErrorReason::UnknownAttribute })?
+            }
+        }
+
+        Ok(body)
+    }
+
+    fn simulation(&mut self, line: usize, column: usize, tokens: &[(usize, &str)]) -> Result<Option<SimulationSettings>, ParseError> {
+        let mut step = None;
+        let mut settings = SimulationSettings::new(1.0);
+        for (column, key, value_column, value) in self.attributes(line, tokens)? {
+            match key {
+                "step" => {
+                    let value = self.number(line, value_column, value)?;
+                    if value > 0.0 {
+                        step = Some(value);
+                    } else {
+                        self.report(ParseError { line, column: value_column, token: value.to_string(), reason: ParseErrorReason::NonPositiveStep })?;
+                    }
+                }
+                "G" => settings.gravity = self.number(line, value_column, value)?,
+                "softening" => settings.softening = self.number(line, value_column, value)?,
+                "opening_angle" => settings.force = ForceEvaluator::BarnesHut { opening_angle: self.number(line, value_column, value)? },
+                "tolerance" => {
+                    let value = self.number(line, value_column, value)?;
+                    if value > 0.0 {
+                        settings.tolerance = value;
+                    } else {
+                        self.report(ParseError { line, column: value_column, token: value.to_string(), reason: ParseErrorReason::NonPositiveTolerance })?;
+                    }
+                }
+                "integrator" => match Integrator::parse(value) {
+                    Some(integrator) => settings.integrator = integrator,
+                    None => self.report(ParseError { line, column: value_column, token: value.to_string(), reason: ParseErrorReason::UnknownIntegrator })?
+                },
+                _ => self.report(ParseError { line, column, token: key.to_string(), reason: ParseErrorReason::UnknownAttribute })?
+            }
+        }
+
+        let Some(step) = step else {
+            self.report(ParseError { line, column, token: "step".to_string(), reason: ParseErrorReason::MissingAttribute })?;
+            return Ok(None);
+        };
+
+        settings.step = step;
+        Ok(Some(settings))
+    }
+
+    //Splits key=value tokens into (column, key, value column, value)
+    pub(super) fn attributes<'a>(&mut self, line: usize, tokens: &[(usize, &'a str)]) -> Result<Vec<(usize, &'a str, usize, &'a str)>, ParseError> {
+        let mut attributes = Vec::new();
+        for &(column, token) in tokens {
+            match token.split_once('=') {
+                Some((key, value)) => attributes.push((column, key, column + key.chars().count() + 1, value)),
+                None => self.report(ParseError { line, column, token: token.to_string(), reason: ParseErrorReason::UnknownAttribute })?
+            }
+        }
+        Ok(attributes)
+    }
+
+    pub(super) fn number(&mut self, line: usize, column: usize, token: &str) -> Result<f64, ParseError> {
+        let reason = match token.parse::<f64>() {
+            Ok(value) if value.is_finite() => return Ok(value),
+            Ok(_) => ParseErrorReason::NonFiniteNumber,
+            Err(_) => ParseErrorReason::InvalidNumber
+        };
+
+        self.report(ParseError { line, column, token: token.to_string(), reason })?;
+        Ok(0.0)
+    }
+
+    pub(super) fn report(&mut self, error: ParseError) -> Result<(), ParseError> {
+        match self.mode {
+            ParseMode::Strict => Err(error),
+            ParseMode::Lenient => {
+                self.warnings += 1;
+                if self.warnings <= MAX_WARNINGS {
+                    warn!("{error}");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+//Writes the trajectory in the format parse reads
+pub(super) fn write(trajectory: &Trajectory, writer: &mut impl Write) -> std::io::Result<()> {
+    let frames = || (0..trajectory.len()).filter_map(|index| trajectory.frame(index));
+    let timed = !trajectory.is_empty() && frames().all(|frame| frame.time.is_some());
+    let with_velocities = !trajectory.is_empty() && frames().all(|frame| frame.velocities.is_some());
+    let with_ids = frames().any(|frame| frame.ids.is_some());
+
+    //With ids every body needs a name to be referenced by, unnamed bodies are named after their index
+    let name = |body: usize| trajectory.bodies.get(body)
+        .and_then(|body| body.name.as_deref())
+        .map_or_else(|| body.to_string(), |name| name.replace(char::is_whitespace, "_"));
+    for (index, body) in trajectory.bodies.iter().enumerate() {
+        match body.name {
+            None if with_ids => writeln!(writer, "{}", Body { name: Some(name(index)), ..body.clone() })?,
+            _ => writeln!(writer, "{body}")?
+        }
+    }
+
+    if let Some(settings) = trajectory.simulation {
+        write!(writer, "# simulate step={:e} G={:e} softening={:e} integrator={} tolerance={:e}",
+            settings.step, settings.gravity, settings.softening, settings.integrator, settings.tolerance)?;
+        if let ForceEvaluator::BarnesHut { opening_angle } = settings.force {
+            write!(writer, " opening_angle={opening_angle}")?;
+        }
+        writeln!(writer)?;
+    }
+
+    for event in &trajectory.events {
+        if let EventKind::Merge { body, into } = event.kind {
+            writeln!(writer, "# merge {} {} at={:e}", name(body), name(into), event.time)?;
+        }
+    }
+
+    for (directive, enabled) in [("time", timed), ("velocities", with_velocities), ("ids", with_ids)] {
+        if enabled {
+            writeln!(writer, "# {directive}")?;
+        }
+    }
+
+    for frame in frames() {
+        let mut line = String::new();
+        if let Some(time) = frame.time.filter(|_| timed) {
+            line.push_str(&format!("{time:e}"));
+        }
+
+        for (entry, position) in frame.positions.iter().enumerate() {
+            if with_ids {
+                line.push_str(&format!(" {}", name(frame.body(entry))));
+            }
+            let velocity = frame.velocities.as_ref().filter(|_| with_velocities).map(|velocities| velocities[entry]);
+            for value in position.iter().chain(velocity.iter().flatten()) {
+                line.push_str(&format!(" {value:e}"));
+            }
+        }
+        writeln!(writer, "{}", line.trim_start())?;
+    }
+
+    writer.flush()
+}
+
+fn parse_color(value: &str) -> Option<[f32; 4]> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut color = [1.0; 4];
+    for (channel, index) in color.iter_mut().zip((0..6).step_by(2)) {
+        *channel = u8::from_str_radix(&hex[index..index + 2], 16).ok()? as f32 / 255.0;
+    }
+    Some(color)
+}
+
+impl Display for Body {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = self.name.as_deref().unwrap_or("-").replace(char::is_whitespace, "_");
+        write!(f, "# body {name}")?;
+        if let Some(mass) = self.mass {
+            write!(f, " mass={mass:e}")?;
+        }
+        if let Some(radius) = self.radius {
+            write!(f, " radius={radius:e}")?;
+        }
+        if let Some(color) = self.color {
+            let [r, g, b, _] = color.map(|channel| (channel * 255.0).round() as u8);
+            write!(f, " color=#{r:02x}{g:02x}{b:02x}")?;
+        }
+        if let Some(light) = self.light {
+            write!(f, " light={light}")?;
+        }
+        if let Some(texture) = &self.texture {
+            write!(f, " texture={texture}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ParseErrorReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorReason::InvalidNumber => write!(f, "invalid number"),
+            ParseErrorReason::NonFiniteNumber => write!(f, "number is not finite"),
+            ParseErrorReason::IncompleteEntry => write!(f, "incomplete position or velocity"),
+            ParseErrorReason::MissingName => write!(f, "body is missing a name"),
+            ParseErrorReason::UnknownAttribute => write!(f, "unknown attribute"),
+            ParseErrorReason::InvalidColor => write!(f, "invalid color, expected #rrggbb"),
+            ParseErrorReason::MissingColumn => write!(f, "missing column"),
+            ParseErrorReason::MissingField => write!(f, "row has too few fields"),
+            ParseErrorReason::DuplicateBody => write!(f, "body appears twice in the same frame"),
+            ParseErrorReason::DecreasingTime => write!(f, "time is smaller than in the previous frame"),
+            ParseErrorReason::LateDirective => write!(f, "directive has to appear before the first frame"),
+            ParseErrorReason::UnknownBody => write!(f, "unknown body id"),
+            ParseErrorReason::MissingAttribute => write!(f, "missing attribute"),
+            ParseErrorReason::NonPositiveStep => write!(f, "step has to be positive"),
+            ParseErrorReason::NonPositiveTolerance => write!(f, "tolerance has to be positive"),
+            ParseErrorReason::TooManyFrames => write!(f, "span has more than {} frames", Span::MAX_FRAMES),
+            ParseErrorReason::MissingVelocities => write!(f, "a simulation needs velocities or timestamps to start from"),
+            ParseErrorReason::UnknownIntegrator => write!(f, "unknown integrator, expected leapfrog or rk45"),
+            ParseErrorReason::UnboundOrbit => write!(f, "orbit is not bound, expected a > 0 and 0 <= e < 1"),
+            ParseErrorReason::MissingCentralBody => write!(f, "missing # central body")
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.reason)?;
+        if !self.token.is_empty() {
+            write!(f, " `{}`", self.token)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {}
@@ -0,0 +1,112 @@
+use log::error;
+use std::sync::Arc;
+use wasm_bindgen::prelude::wasm_bindgen;
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopProxy};
+use web_sys::{window, HtmlCanvasElement};
+use winit::platform::web::WindowAttributesExtWebSys;
+use winit::window::{Window, WindowAttributes};
+use wasm_bindgen::JsCast;
+use crate::canvas::Canvas;
+use crate::entry::{self, Application};
+use crate::physics::Preset;
+use crate::trajectory::{CsvColumns, ParseMode, Span, Trajectory};
+
+#[wasm_bindgen(start)]
+pub fn start() {
+    console_log::init_with_level(log::Level::Info).ok();
+    std::panic::set_hook(Box::new(|panic_info| {
+        error!("{panic_info}");
+    }));
+
+    let event_loop = EventLoop::with_user_event().build().unwrap();
+    let mut application = Application::new(event_loop.create_proxy());
+    event_loop.set_control_flow(ControlFlow::Wait);
+    event_loop.run_app(&mut application).unwrap();
+}
+
+impl Application {
+    fn new(proxy: EventLoopProxy<Canvas>) -> Application {
+        let canvas = window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.get_element_by_id("canvas"))
+            .and_then(|canvas| canvas.dyn_into::<HtmlCanvasElement>().ok());
+
+        let window_attributes = WindowAttributes::default()
+            .with_prevent_default(true)
+            .with_focusable(true)
+            .with_append(true)
+            .with_canvas(canvas);
+
+        Application::Uninitialized(Box::new(window_attributes), proxy)
+    }
+
+    pub(super) fn init(proxy: EventLoopProxy<Canvas>, window: Arc<Window>) {
+        wasm_bindgen_futures::spawn_local(async move {
+            let size = window.inner_size();
+            let canvas = Canvas::new(window, size).await;
+            proxy.send_event(canvas).ok();
+        });
+    }
+}
+
+#[wasm_bindgen]
+pub fn handle_file_conent(content: String, lenient: bool) -> Result<(), String> {
+    let mode = if lenient { ParseMode::Lenient } else { ParseMode::Strict };
+    let trajectory = Trajectory::parse(&content, mode).map_err(|err| err.to_string())?;
+    *entry::CONTENT.lock().unwrap() = Some(trajectory);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn handle_csv_content(content: String, columns: Option<String>, lenient: bool) -> Result<(), String> {
+    let mode = if lenient { ParseMode::Lenient } else { ParseMode::Strict };
+    let columns = match columns {
+        Some(names) => CsvColumns::parse(&names).ok_or("columns must be time,body,x,y,z[,vx,vy,vz]")?,
+        None => CsvColumns::default()
+    };
+
+    let trajectory = Trajectory::parse_csv(&content, &columns, mode).map_err(|err| err.to_string())?;
+    *entry::CONTENT.lock().unwrap() = Some(trajectory);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn handle_elements_content(content: String, span: Option<String>, lenient: bool) -> Result<(), String> {
+    let mode = if lenient { ParseMode::Lenient } else { ParseMode::Strict };
+    let span = match span {
+        Some(span) => Some(Span::parse(&span)
+            .ok_or_else(|| format!("span must be start,end,step with a positive step and at most {} frames", Span::MAX_FRAMES))?),
+        None => None
+    };
+
+    let trajectory = Trajectory::parse_elements(&content, span, mode).map_err(|err| err.to_string())?;
+    *entry::CONTENT.lock().unwrap() = Some(trajectory);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn handle_binary_content(content: Vec<u8>) -> Result<(), String> {
+    let trajectory = Trajectory::from_binary(content).map_err(|err| err.to_string())?;
+    *entry::CONTENT.lock().unwrap() = Some(trajectory);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn load_preset(name: String, seed: u32, count: Option<u32>) -> Result<(), String> {
+    let preset = Preset::parse(&name).ok_or("preset must be solar-system, figure-eight, disk, plummer or circumbinary")?;
+    let trajectory = preset.generate(seed as u64, count.map(|count| count as usize));
+    *entry::CONTENT.lock().unwrap() = Some(trajectory);
+    Ok(())
+}
+
+//Closest approaches below this distance are added to the event log of the next loaded trajectory
+#[wasm_bindgen]
+pub fn set_encounter_distance(distance: Option<f64>) {
+    *entry::ENCOUNTER_DISTANCE.lock().unwrap() = distance.filter(|&distance| distance > 0.0);
+}
+
+//Registers a PNG or JPEG under its file name for the trajectories loaded afterwards
+#[wasm_bindgen]
+pub fn load_texture(name: String, content: Vec<u8>) {
+    entry::TEXTURES.lock().unwrap().insert(name, content);
+}
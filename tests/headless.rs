@@ -0,0 +1,209 @@
+//Loading and playback through the library alone, without a window or GPU
+use std::time::Duration;
+use wasm::clock::FixedStepClock;
+use wasm::physics::{self, ForceEvaluator, Preset, Simulation};
+use wasm::system::System;
+use wasm::trajectory::{CsvColumns, EventKind, ParseMode, Precision, Span, Trajectory};
+
+const ASTRONOMICAL_UNIT: f64 = 1.495978707e11;
+
+fn fixed_step(trajectory: Trajectory) -> System {
+    let mut system = System::new(trajectory);
+    system.set_clock(Box::new(FixedStepClock::new(Duration::from_millis(100))));
+    system
+}
+
+#[test]
+fn loads_bodies_and_frames() {
+    let content = "# body Sun mass=2e30\n# body Earth mass=6e24\n# time\n0 0 0 0 1 0 0\n10 0 0 0 0 1 0\n";
+    let trajectory = Trajectory::parse(content, ParseMode::Strict).unwrap();
+    assert_eq!(trajectory.len(), 2);
+    assert_eq!(trajectory.name(1), "Earth");
+    assert_eq!(trajectory.frame(1).unwrap().positions[1], [0.0, 1.0, 0.0]);
+}
+
+#[test]
+fn rejects_a_tolerance_that_is_not_positive() {
+    for tolerance in ["0", "-1e-9"] {
+        let content = format!("# simulate step=1 integrator=rk45 tolerance={tolerance}\n# velocities\n0 0 0 0 0 0\n");
+        assert!(Trajectory::parse(&content, ParseMode::Strict).is_err());
+    }
+}
+
+#[test]
+fn csv_bodies_come_and_go_with_their_rows() {
+    let content = "t,body,x,y,z\n0,a,0,0,0\n0,b,1,0,0\n1,a,0,1,0\n2,b,2,0,0\n2,a,0,2,0\n";
+    let trajectory = Trajectory::parse_csv(content, &CsvColumns::default(), ParseMode::Strict).unwrap();
+    assert_eq!(trajectory.frame(1).unwrap().positions, [[0.0, 1.0, 0.0]]);
+    assert_eq!(trajectory.frame(2).unwrap().ids, Some(vec![0, 1]));
+    let kinds = trajectory.events.iter().map(|event| (event.time, event.kind)).collect::<Vec<_>>();
+    assert_eq!(kinds, [(1.0, EventKind::Death(1)), (2.0, EventKind::Birth(1))]);
+}
+
+#[test]
+fn diagnostics_need_a_time_for_the_kinetic_energy() {
+    let bodies = "# body A mass=1\n# body B mass=1\n";
+    let untimed = Trajectory::parse(&format!("{bodies}0 0 0 1 0 0\n0 1 0 1 1 0\n"), ParseMode::Strict).unwrap();
+    let diagnostics = physics::analyze(&untimed);
+    assert_eq!(diagnostics.iter().map(|frame| frame.frame).collect::<Vec<_>>(), [0, 1]);
+    assert!(diagnostics.iter().all(|frame| frame.kinetic.is_none() && frame.potential < 0.0));
+    assert_eq!(physics::drift(&diagnostics).energy, None);
+
+    let timed = Trajectory::parse(&format!("{bodies}# time\n0 0 0 0 1 0 0\n2 0 1 0 1 1 0\n"), ParseMode::Strict).unwrap();
+    let current = physics::diagnose(&timed, 1).unwrap();
+    assert_eq!((current.frame, current.time), (1, 2.0));
+    assert!(current.kinetic.is_some_and(|kinetic| kinetic > 0.0));
+}
+
+#[test]
+fn elements_generate_a_limited_number_of_frames() {
+    let content = "# central Sun mass=2e30\n# span start=0 end=1e9 step=1\nEarth 1.5e11 0.0167 0 0 0 0\n";
+    assert!(Trajectory::parse_elements(content, None, ParseMode::Strict).is_err());
+    let trajectory = Trajectory::parse_elements(content, None, ParseMode::Lenient).unwrap();
+    assert_eq!(trajectory.len(), Span::MAX_FRAMES);
+    assert!(Span::parse("0,1e9,1").is_none());
+}
+
+#[test]
+fn selects_bodies_without_a_header() {
+    let mut system = System::new(Trajectory::parse("0 0 0 1 0 0\n", ParseMode::Strict).unwrap());
+    system.select_next_body();
+    assert_eq!(system.orbit_status().as_deref(), Some("body 0: no orbit"));
+    system.select_next_body();
+    assert!(system.orbit_status().is_some_and(|status| status.starts_with("body 1")));
+    system.select_next_body();
+    assert_eq!(system.orbit_status(), None);
+}
+
+#[test]
+fn detects_events_without_a_header() {
+    let content = "# time\n0 0 0 1 5 0 0\n1 0 0 -1 1 0 0\n2 0 0 1 5 0 0\n";
+    let mut system = System::new(Trajectory::parse(content, ParseMode::Strict).unwrap());
+    system.detect_events(Some(2.0));
+    system.scan_events();
+    let kinds = system.trajectory().events.iter().map(|event| event.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, [
+        EventKind::PlaneCrossing { body: 0, ascending: false },
+        EventKind::Encounter { body: 0, other: 1, distance: 2f64.sqrt() },
+        EventKind::PlaneCrossing { body: 0, ascending: true }
+    ]);
+}
+
+#[test]
+fn rejects_an_id_without_values() {
+    let content = "# ids\nA 1 2 3 B\n";
+    assert!(Trajectory::parse(content, ParseMode::Strict).is_err());
+}
+
+#[test]
+fn merges_where_the_body_disappears() {
+    let content = "# ids\n# body A\n# body B\n# merge A B at=1.5\n# time\n0 A 0 0 0 B 1 0 0\n1 A 0 0 0 B 1 0 0\n2 B 1 0 0\n";
+    let trajectory = Trajectory::parse(content, ParseMode::Strict).unwrap();
+    let kinds = trajectory.events.iter().map(|event| (event.time, event.kind)).collect::<Vec<_>>();
+    assert_eq!(kinds, [(2.0, EventKind::Merge { body: 0, into: 1 })]);
+}
+
+#[test]
+fn binary_keeps_bodies_that_come_and_go() {
+    let trajectory = Trajectory::parse("# time\n0 0 0 0\n1 0 0 0 1 0 0\n2 0 0 0\n", ParseMode::Strict).unwrap();
+    let path = std::env::temp_dir().join(format!("headless-{}.pvt", std::process::id()));
+    trajectory.write_binary(&mut std::fs::File::create(&path).unwrap(), Precision::Double).unwrap();
+    let read = Trajectory::open_binary(path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read.events, trajectory.events);
+    assert_eq!(read.events.len(), 2);
+    assert_eq!(read.frame(2).unwrap().positions, [[0.0; 3]]);
+}
+
+#[test]
+fn simulation_starts_with_differenced_velocities() {
+    let content = "# body A mass=1\n# simulate step=0.1\n# time\n0 0 0 0\n1 1 0 0\n2 2 0 0\n";
+    let trajectory = Trajectory::parse(content, ParseMode::Strict).unwrap();
+    let simulation = Simulation::new(trajectory.simulation.unwrap(), &trajectory);
+    assert_eq!(simulation.state().velocities, [[1.0, 0.0, 0.0]]);
+
+    let untimed = "# body A mass=1\n# simulate step=0.1\n0 0 0\n1 0 0\n";
+    assert!(Trajectory::parse(untimed, ParseMode::Strict).is_err());
+}
+
+#[test]
+fn keeps_a_bounded_step_history() {
+    let content = "# body A mass=1\n# body B mass=1e-3\n# simulate step=1e-3 G=1 integrator=rk45 tolerance=1e-12\n# velocities\n0 0 0 0 0 0 1 0 0 0 1 0\n";
+    let trajectory = Trajectory::parse(content, ParseMode::Strict).unwrap();
+    let mut simulation = Simulation::new(trajectory.simulation.unwrap(), &trajectory);
+    for _ in 0..10_000 {
+        simulation.advance();
+    }
+
+    let history = simulation.history();
+    assert_eq!(history.count, 10_000);
+    assert!(history.samples().len() <= 4096 && history.stride() > 1);
+    assert_eq!(history.samples().len(), history.count.div_ceil(history.stride()));
+    let smallest = history.samples().iter().map(|&(_, step)| step).fold(f64::INFINITY, f64::min);
+    assert_eq!(smallest, history.smallest);
+    assert!(history.samples().windows(2).all(|pair| pair[0].0 < pair[1].0));
+}
+
+#[test]
+fn detects_events_as_playback_reaches_them() {
+    let content = "# time\n0 0 0 1\n1 0 0 -1\n2 0 0 1\n";
+    let mut system = fixed_step(Trajectory::parse(content, ParseMode::Strict).unwrap());
+    system.detect_events(None);
+    assert!(system.trajectory().events.is_empty());
+    system.step();
+    assert_eq!(system.trajectory().events.len(), 1);
+    system.step();
+    assert_eq!(system.trajectory().events.len(), 2);
+}
+
+#[test]
+fn advances_between_frames() {
+    let trajectory = Trajectory::parse("# time\n0 0 0 0\n10 10 0 0\n", ParseMode::Strict).unwrap();
+    let mut system = fixed_step(trajectory);
+    //A tick of 100 ms plays one average step, half of it once slowed down
+    system.slow_down();
+    let frame = system.step();
+    assert_eq!(system.time(), 5.0);
+    assert_eq!(frame.positions[0], [5.0, 0.0, 0.0]);
+}
+
+#[test]
+fn advances_a_live_simulation() {
+    let mut system = fixed_step(Preset::parse("figure-eight").unwrap().generate(0, None));
+    let start = system.trajectory().len();
+    system.step();
+    assert!(system.trajectory().len() > start);
+    assert!(system.time() > 0.0);
+}
+
+#[test]
+fn selects_a_body_and_its_orbit() {
+    let mut system = fixed_step(Preset::parse("solar-system").unwrap().generate(0, None));
+    assert!(system.osculating().is_none());
+    while !system.orbit_status().is_some_and(|status| status.starts_with("Earth")) {
+        system.select_next_body();
+    }
+
+    let elements = system.osculating().unwrap().elements;
+    assert!((elements.semi_major_axis / ASTRONOMICAL_UNIT - 1.0).abs() < 0.01, "{}", elements.semi_major_axis);
+    assert!((elements.eccentricity - 0.0167).abs() < 0.005, "{}", elements.eccentricity);
+}
+
+#[test]
+fn decimates_keeping_the_ends() {
+    let content = (0..6).map(|time| format!("{time} {time} 0 0\n")).collect::<String>();
+    let mut trajectory = Trajectory::parse(&format!("# time\n{content}"), ParseMode::Strict).unwrap();
+    trajectory.decimate();
+    let times = (0..trajectory.len()).map(|index| trajectory.time(index).unwrap()).collect::<Vec<_>>();
+    assert_eq!(times, [0.0, 1.0, 3.0, 5.0]);
+}
+
+#[test]
+fn barnes_hut_stays_close_to_direct_summation() {
+    //Bounds for a uniform sphere of 1000 bodies, the error shrinks with the opening angle
+    for (opening_angle, median, max) in [(0.3, 1e-3, 1e-2), (0.5, 1e-2, 1e-1)] {
+        let comparison = physics::compare(ForceEvaluator::BarnesHut { opening_angle }, 1000, 1);
+        assert!(comparison.median_error < median, "median error {:e} at θ={opening_angle}", comparison.median_error);
+        assert!(comparison.max_error < max, "max error {:e} at θ={opening_angle}", comparison.max_error);
+    }
+}